@@ -1,14 +1,20 @@
 use anyhow::{Context, Result};
+#[cfg(feature = "filedb")]
+use meigen_bot_rust::db::file::FileMeigenDatabase;
 #[cfg(feature = "memorydb")]
 use meigen_bot_rust::db::mem::MemoryMeigenDatabase;
 #[cfg(feature = "mongodb_")]
 use meigen_bot_rust::db::mongo::MongoMeigenDatabase;
 use meigen_bot_rust::entrypoint::console::Console;
 
-#[cfg(all(not(feature = "memorydb"), not(feature = "mongodb_")))]
-compile_error!("memorydb OR mongodb must be enabled, not both.");
-#[cfg(all(feature = "memorydb", feature = "mongodb_"))]
-compile_error!("memorydb OR mongodb must be enabled, not both.");
+#[cfg(not(any(feature = "memorydb", feature = "mongodb_", feature = "filedb")))]
+compile_error!("one of memorydb, mongodb or filedb must be enabled.");
+#[cfg(any(
+    all(feature = "memorydb", feature = "mongodb_"),
+    all(feature = "memorydb", feature = "filedb"),
+    all(feature = "mongodb_", feature = "filedb"),
+))]
+compile_error!("only one of memorydb, mongodb or filedb can be enabled.");
 
 fn main() -> Result<()> {
     dotenv::dotenv().ok();
@@ -40,6 +46,14 @@ async fn async_main() -> Result<()> {
         .await
         .context("failed to get mongodb instance")?;
 
+    #[cfg(feature = "filedb")]
+    let db = {
+        let path = std::env::var("FILEDB_PATH").unwrap_or_else(|_| "meigen.json".into());
+        FileMeigenDatabase::new(path)
+            .await
+            .context("failed to open database file")?
+    };
+
     Console::new(db).run().await;
 
     Ok(())
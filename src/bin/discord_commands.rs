@@ -0,0 +1,31 @@
+//! prints the commands to be registered to discord.
+//! with `--check`, fails if discord_slash_command.json is not the same as them.
+
+use std::process::exit;
+
+use anyhow::{Context, Result};
+use meigen_bot_rust::entrypoint::discord_webhook::commands::application_commands_json;
+
+const JSON_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/discord_slash_command.json");
+
+fn main() -> Result<()> {
+    let generated = application_commands_json()?;
+
+    if std::env::args().nth(1).as_deref() != Some("--check") {
+        print!("{}", generated);
+        return Ok(());
+    }
+
+    let checked_in = std::fs::read_to_string(JSON_PATH)
+        .with_context(|| format!("failed to read {}", JSON_PATH))?;
+
+    if checked_in != generated {
+        eprintln!(
+            "discord_slash_command.json is stale. regenerate it with\n    \
+             cargo run --features discord_webhook --bin discord_commands > discord_slash_command.json"
+        );
+        exit(1);
+    }
+
+    Ok(())
+}
@@ -1,14 +1,27 @@
+use std::time::Duration;
+
 use anyhow::{Context, Result};
+#[cfg(feature = "filedb")]
+use meigen_bot_rust::db::file::FileMeigenDatabase;
 #[cfg(feature = "memorydb")]
 use meigen_bot_rust::db::mem::MemoryMeigenDatabase;
 #[cfg(feature = "mongodb_")]
 use meigen_bot_rust::db::mongo::MongoMeigenDatabase;
-use meigen_bot_rust::entrypoint::discord_webhook::DiscordWebhookServerOptions;
+use meigen_bot_rust::{
+    entrypoint::discord_webhook::{
+        DiscordWebhookServerOptions, DEFAULT_DISCORD_API_BASE_URL, DEFAULT_SIGNATURE_MAX_AGE,
+    },
+    permission::Permissions,
+};
 
-#[cfg(all(not(feature = "memorydb"), not(feature = "mongodb_")))]
-compile_error!("memorydb OR mongodb must be enabled, not both.");
-#[cfg(all(feature = "memorydb", feature = "mongodb_"))]
-compile_error!("memorydb OR mongodb must be enabled, not both.");
+#[cfg(not(any(feature = "memorydb", feature = "mongodb_", feature = "filedb")))]
+compile_error!("one of memorydb, mongodb or filedb must be enabled.");
+#[cfg(any(
+    all(feature = "memorydb", feature = "mongodb_"),
+    all(feature = "memorydb", feature = "filedb"),
+    all(feature = "mongodb_", feature = "filedb"),
+))]
+compile_error!("only one of memorydb, mongodb or filedb can be enabled.");
 
 fn main() -> Result<()> {
     dotenv::dotenv().ok();
@@ -40,6 +53,14 @@ async fn async_main() -> Result<()> {
         .await
         .context("failed to get mongodb instance")?;
 
+    #[cfg(feature = "filedb")]
+    let db = {
+        let path = std::env::var("FILEDB_PATH").unwrap_or_else(|_| "meigen.json".into());
+        FileMeigenDatabase::new(path)
+            .await
+            .context("failed to open database file")?
+    };
+
     let port = std::env::var("PORT")
         .as_ref()
         .map(|x| x.as_str())
@@ -50,6 +71,16 @@ async fn async_main() -> Result<()> {
     DiscordWebhookServerOptions {
         app_public_key: std::env::var("DISCORD_APP_PUBLIC_KEY").unwrap(),
         db,
+        permissions: Permissions::from_env().context("failed to load permissions")?,
+        discord_api_base_url: std::env::var("DISCORD_API_BASE_URL")
+            .unwrap_or_else(|_| DEFAULT_DISCORD_API_BASE_URL.into()),
+        signature_max_age: match std::env::var("DISCORD_SIGNATURE_MAX_AGE_SECS") {
+            Ok(secs) => Duration::from_secs(
+                secs.parse()
+                    .context("DISCORD_SIGNATURE_MAX_AGE_SECS must be a number")?,
+            ),
+            Err(_) => DEFAULT_SIGNATURE_MAX_AGE,
+        },
     }
     .into_server()
     .unwrap()
@@ -1,4 +1,6 @@
 use anyhow::{Context, Result};
+#[cfg(feature = "filedb")]
+use meigen_bot_rust::db::file::FileMeigenDatabase;
 #[cfg(feature = "memorydb")]
 use meigen_bot_rust::db::mem::MemoryMeigenDatabase;
 #[cfg(feature = "mongodb_")]
@@ -7,12 +9,16 @@ use meigen_bot_rust::db::mongo::MongoMeigenDatabase;
 use meigen_bot_rust::entrypoint::api::auth::AlwaysPass;
 #[cfg(not(feature = "api_auth_always_pass"))]
 use meigen_bot_rust::entrypoint::api::auth::GAuth;
-use meigen_bot_rust::entrypoint::api::grpc::GrpcServer;
+use meigen_bot_rust::{entrypoint::api::grpc::GrpcServer, permission::Permissions};
 
-#[cfg(all(not(feature = "memorydb"), not(feature = "mongodb_")))]
-compile_error!("memorydb OR mongodb must be enabled, not both.");
-#[cfg(all(feature = "memorydb", feature = "mongodb_"))]
-compile_error!("memorydb OR mongodb must be enabled, not both.");
+#[cfg(not(any(feature = "memorydb", feature = "mongodb_", feature = "filedb")))]
+compile_error!("one of memorydb, mongodb or filedb must be enabled.");
+#[cfg(any(
+    all(feature = "memorydb", feature = "mongodb_"),
+    all(feature = "memorydb", feature = "filedb"),
+    all(feature = "mongodb_", feature = "filedb"),
+))]
+compile_error!("only one of memorydb, mongodb or filedb can be enabled.");
 
 fn main() -> Result<()> {
     dotenv::dotenv().ok();
@@ -44,6 +50,14 @@ async fn async_main() -> Result<()> {
         .await
         .context("failed to get mongodb instance")?;
 
+    #[cfg(feature = "filedb")]
+    let db = {
+        let path = std::env::var("FILEDB_PATH").unwrap_or_else(|_| "meigen.json".into());
+        FileMeigenDatabase::new(path)
+            .await
+            .context("failed to open database file")?
+    };
+
     let port = std::env::var("PORT")
         .as_ref()
         .map(|x| x.as_str())
@@ -61,7 +75,9 @@ async fn async_main() -> Result<()> {
     #[cfg(feature = "api_auth_always_pass")]
     let authenticator = AlwaysPass;
 
-    GrpcServer::new(db, authenticator)
+    let permissions = Permissions::from_env().context("failed to load permissions")?;
+
+    GrpcServer::new(db, authenticator, permissions)
         .start(([0, 0, 0, 0], port))
         .await
 }
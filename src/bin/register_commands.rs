@@ -0,0 +1,70 @@
+//! registers the commands to discord, creating, updating and deleting only what differs.
+//! with `--dry-run`, only prints what would be changed.
+
+use anyhow::{Context, Result};
+use meigen_bot_rust::entrypoint::discord_webhook::{
+    commands::application_commands,
+    register::{CommandRegistrar, Scope},
+    DEFAULT_DISCORD_API_BASE_URL,
+};
+
+fn main() -> Result<()> {
+    dotenv::dotenv().ok();
+
+    let use_ansi = env_var("NO_COLOR").is_err();
+
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_ansi(use_ansi)
+        .init();
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .context("failed to build tokio runtime")?
+        .block_on(async_main())
+}
+
+fn env_var(name: &str) -> Result<String> {
+    std::env::var(name).with_context(|| format!("failed to get {} environment variable", name))
+}
+
+async fn async_main() -> Result<()> {
+    let dry_run = std::env::args().skip(1).any(|x| x == "--dry-run");
+
+    // registers to the guild if given, otherwise globally.
+    let scope = match std::env::var("DISCORD_GUILD_ID") {
+        Ok(guild_id) => Scope::Guild(guild_id),
+        Err(_) => Scope::Global,
+    };
+
+    let registrar = CommandRegistrar::new(
+        &std::env::var("DISCORD_API_BASE_URL")
+            .unwrap_or_else(|_| DEFAULT_DISCORD_API_BASE_URL.into()),
+        &env_var("DISCORD_APPLICATION_ID")?,
+        env_var("DISCORD_BOT_TOKEN")?,
+        scope,
+    )?;
+
+    let changes = registrar.plan(application_commands()?).await?;
+
+    if changes.is_empty() {
+        println!("the commands are up to date");
+        return Ok(());
+    }
+
+    for change in &changes {
+        println!("{}", change);
+    }
+
+    if dry_run {
+        println!("dry run: nothing was changed");
+        return Ok(());
+    }
+
+    registrar.apply(&changes).await?;
+
+    println!("applied {} change(s)", changes.len());
+
+    Ok(())
+}
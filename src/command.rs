@@ -1,18 +1,24 @@
+pub mod registry;
+
 use anyhow::{anyhow, Context as _, Result};
-use rand::{prelude::SmallRng, Rng, SeedableRng};
 
+use self::registry::Syntax;
 use crate::{
-    db::{FindOptions, MeigenDatabase},
-    model::Meigen,
+    db::{FindOptions, FindResult, MeigenDatabase, NotEnoughMeigens, SortOrder},
+    model::{AuthorStats, Meigen, MeigenSource},
+    permission::{Action, Actor, Permissions},
+    query::{Query, QUERY_LENGTH_LIMIT},
     util::IteratorEditExt,
     Synced,
 };
 
-const MEIGEN_LENGTH_LIMIT: usize = 300;
+pub(crate) const MEIGEN_LENGTH_LIMIT: usize = 300;
 const LIST_LENGTH_LIMIT: usize = 400;
 
 trait IterExt {
     fn fold_list(self) -> Option<String>;
+    /// like `fold_list`, but omits the tail instead of the head. for rankings, whose head matters most.
+    fn fold_ranking(self) -> Option<String>;
 }
 
 impl<T, D> IterExt for T
@@ -43,58 +49,224 @@ where
             _ => Some(text),
         }
     }
+
+    fn fold_ranking(self) -> Option<String> {
+        let (mut text, len) = self.fold((String::new(), 0), |(mut text, mut len), item| {
+            if len < LIST_LENGTH_LIMIT {
+                let item = format!("{}\n", item);
+
+                text.push_str(&item);
+                len += item.chars().count() + 1;
+            }
+
+            (text, len)
+        });
+
+        if len >= LIST_LENGTH_LIMIT {
+            text.push_str("結果が長すぎたため、一部は省略されました。\n");
+        }
+
+        match len {
+            0 => None,
+            _ => Some(text),
+        }
+    }
 }
 
-/// clamps number, returns clamped number and message which should be sent to User
-macro_rules! option {
-    ({value: $value:ident, default: $default:literal, min: $min:literal, max: $max:literal $(,)?}) => {{
-        match $value.unwrap_or($default) {
-            n if n > $max => (
-                $max,
-                concat!(
-                    stringify!($value),
-                    "の値は大きすぎたため",
-                    stringify!($max),
-                    "に丸められました。\n"
-                ),
-            ),
+/// why a command could not be done. frontends with their own error codes (e.g. http status) map these.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandError {
+    /// the meigen or the page does not exist.
+    NotFound,
+    Forbidden,
+    /// the meigen exceeds MEIGEN_LENGTH_LIMIT.
+    TooLong,
+    /// e.g. a malformed query, or no field to edit.
+    InvalidArgument,
+    /// the operation was already done, e.g. loving the same meigen twice.
+    Conflict,
+}
 
-            n if n < $min => (
-                $max,
-                concat!(
-                    stringify!($value),
-                    "の値は小さすぎたため",
-                    stringify!($max),
-                    "に丸められました。\n"
-                ),
-            ),
+/// what a command replies. frontends render it in their own way.
+pub struct CommandOutput {
+    /// the whole reply as plain text, including `meigens`.
+    pub text: String,
+    /// the reply without `meigens`, e.g. the page number.
+    /// frontends which show `meigens` by themselves (e.g. discord embeds) send this instead of `text`.
+    pub note: String,
+    pub meigens: Vec<Meigen>,
+    /// Some if the command could not be done, e.g. the user is not allowed or the meigen is missing.
+    pub error: Option<CommandError>,
+    /// the number of all matching items, e.g. the search hits or the registered meigens. 0 if not counted.
+    pub total: u32,
+    /// 1-based. frontends which can edit the sent message (e.g. buttons on discord) use these to move between pages.
+    pub page: u32,
+    /// 0 if the reply is not a list.
+    pub total_pages: u32,
+    /// Some if `meigens` are ranked, e.g. by loves. the rank of the first one, 1-based.
+    pub first_rank: Option<u32>,
+    /// the authors in the author ranking, for frontends which show them by themselves (e.g. the api).
+    pub authors: Vec<AuthorStats>,
+}
+
+impl CommandOutput {
+    pub fn message(text: impl Into<String>) -> Self {
+        let text = text.into();
+
+        Self {
+            note: text.clone(),
+            text,
+            meigens: vec![],
+            error: None,
+            total: 0,
+            page: 1,
+            total_pages: 0,
+            first_rank: None,
+            authors: vec![],
+        }
+    }
+
+    pub fn error(kind: CommandError, text: impl Into<String>) -> Self {
+        Self {
+            error: Some(kind),
+            ..Self::message(text)
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.error.is_some()
+    }
+
+    pub fn meigens(note: impl Into<String>, meigens: Vec<Meigen>) -> Self {
+        let note = note.into();
+        let text = format!("{}{}", note, meigens.iter().fold_list().unwrap_or_default());
 
-            n => (n, ""),
+        Self {
+            text,
+            note,
+            meigens,
+            ..Self::message("")
         }
-    }};
+    }
+
+    fn prepend(&mut self, text: &str) {
+        self.text.insert_str(0, text);
+        self.note.insert_str(0, text);
+    }
 }
 
-pub async fn help() -> Result<String> {
-    const HELP_TEXT: &str = "```asciidoc
-= meigen-bot-rust =
-g!meigen [subcommand] [args...]
-= subcommands =
-    help                                    :: この文を出します
-    make [作者] [名言]                       :: 名言を登録します
-    list [表示数=5] [ページ=1]                :: 名言をリスト表示します
-    id [名言ID]                             :: 指定されたIDの名言を表示します
-    search [検索内容] [表示数=5] [ページ=1]    :: 名言を検索します(g!meigen searchでヘルプを表示します)
-    random [表示数=1]                       :: ランダムに名言を出します
-    love [名言ID]                          :: 名言にいいねをします
-    unlove [名言ID]                        :: 名言のいいねを消します
-    status                                 :: 現在登録されてる名言の数を出します
-    delete [名言ID]                         :: 指定されたIDの名言を削除します かわえもんにしか使えません
-```";
-
-    Ok(HELP_TEXT.into())
+/// what the user asked for. every frontend parses its input into this (see `registry`) and renders the CommandOutput,
+/// so that a new subcommand only has to be added here and in the registry.
+/// None in count or page means the default of each command. out of range values are clamped.
+pub enum Command {
+    /// the help text is written in the syntax of the frontend.
+    Help(Syntax),
+    Status,
+    Make {
+        author: String,
+        content: String,
+        /// the message which the meigen was quoted from.
+        source: Option<MeigenSource>,
+    },
+    Edit {
+        id: u32,
+        author: Option<String>,
+        content: Option<String>,
+    },
+    Id(u32),
+    Gophersay(u32),
+    List {
+        count: Option<u8>,
+        page: Option<u32>,
+    },
+    Search {
+        by: SearchBy,
+        count: Option<u8>,
+        page: Option<u32>,
+    },
+    Random {
+        count: Option<u8>,
+    },
+    Ranking {
+        count: Option<u8>,
+        page: Option<u32>,
+    },
+    AuthorRanking {
+        count: Option<u8>,
+        page: Option<u32>,
+    },
+    Love(u32),
+    Unlove(u32),
+    Delete(u32),
+    Restore(u32),
+    Audit {
+        count: Option<u8>,
+    },
 }
 
-pub async fn status(db: Synced<impl MeigenDatabase>) -> Result<String> {
+pub enum SearchBy {
+    Author(String),
+    Content(String),
+    /// see query.rs for the syntax.
+    Query(String),
+}
+
+impl Command {
+    /// Err is returned only for internal errors. the errors caused by the user are in CommandOutput::error.
+    pub async fn run(
+        self,
+        db: Synced<impl MeigenDatabase>,
+        permissions: &Permissions,
+        actor: &Actor,
+    ) -> Result<CommandOutput> {
+        match self {
+            Command::Help(syntax) => help(syntax).await,
+            Command::Status => status(db).await,
+            Command::Make {
+                author,
+                content,
+                source,
+            } => make(db, &author, &content, actor.user_id, source).await,
+            Command::Edit {
+                id,
+                author,
+                content,
+            } => {
+                edit(
+                    db,
+                    permissions,
+                    id,
+                    author.as_deref(),
+                    content.as_deref(),
+                    actor,
+                )
+                .await
+            }
+            Command::Id(x) => id(db, x).await,
+            Command::Gophersay(x) => gophersay(db, x).await,
+            Command::List { count, page } => list(db, count, page).await,
+            Command::Search { by, count, page } => match by {
+                SearchBy::Author(x) => search_author(db, &x, count, page).await,
+                SearchBy::Content(x) => search_content(db, &x, count, page).await,
+                SearchBy::Query(x) => search_query(db, &x, count, page).await,
+            },
+            Command::Random { count } => random(db, count).await,
+            Command::Ranking { count, page } => ranking(db, count, page).await,
+            Command::AuthorRanking { count, page } => author_ranking(db, count, page).await,
+            Command::Love(x) => love(db, x, actor.user_id).await,
+            Command::Unlove(x) => unlove(db, x, actor.user_id).await,
+            Command::Delete(x) => delete(db, permissions, x, actor).await,
+            Command::Restore(x) => restore(db, permissions, x, actor).await,
+            Command::Audit { count } => audit(db, permissions, count, actor).await,
+        }
+    }
+}
+
+async fn help(syntax: Syntax) -> Result<CommandOutput> {
+    Ok(CommandOutput::message(registry::help_text(syntax)))
+}
+
+async fn status(db: Synced<impl MeigenDatabase>) -> Result<CommandOutput> {
     let count = db
         .read()
         .await
@@ -102,197 +274,517 @@ pub async fn status(db: Synced<impl MeigenDatabase>) -> Result<String> {
         .await
         .context("Failed to fetch meigen count")?;
 
-    Ok(format!(
-        "```yaml
+    Ok(CommandOutput {
+        total: count,
+        ..CommandOutput::message(format!(
+            "```yaml
 total_count: {}
 ```",
-        count
-    ))
+            count
+        ))
+    })
 }
 
-pub async fn random(db: Synced<impl MeigenDatabase>, count: Option<u8>) -> Result<String> {
-    let (count, clamp_msg) = option!({
-        value: count,
-        default: 1,
-        min: 1,
-        max: 5,
-    });
+async fn random(db: Synced<impl MeigenDatabase>, count: Option<u8>) -> Result<CommandOutput> {
+    let (count, clamp_msg) = registry::RANDOM_COUNT.clamp("count", count);
 
-    let count = count as usize;
-    let max = db.read().await.get_current_id().await?;
+    let meigens = match db.read().await.sample(count as u32).await {
+        Ok(m) => m,
+        Err(e) if e.is::<NotEnoughMeigens>() => {
+            return Ok(CommandOutput::error(
+                CommandError::InvalidArgument,
+                "countが総名言数を超えています。",
+            ))
+        }
+        Err(e) => return Err(e).context("failed to sample meigens"),
+    };
 
-    if count > max as usize {
-        return Ok("countが総名言数を超えています。".into());
+    if meigens.is_empty() {
+        return Err(anyhow!("random::get_random didn't bring any meigen"));
     }
 
-    let mut rng = SmallRng::from_rng(&mut rand::thread_rng()).unwrap();
-
-    let mut meigens = Vec::<Meigen>::with_capacity(count);
+    Ok(CommandOutput::meigens(clamp_msg, meigens))
+}
 
-    while meigens.len() != count {
-        let want = meigens.len() - count;
-        let mut try_fetch = Vec::with_capacity(want);
+/// strips characters which break the code block, then checks the length limit.
+/// returns None if the meigen is too long.
+fn prepare_meigen(author: &str, content: &str) -> Option<(String, String)> {
+    let strip = |s: &str| s.replace("`", "");
 
-        loop {
-            let new_id_candidate = rng.gen_range(1..=max);
+    let author = strip(author);
+    let content = strip(content);
 
-            if try_fetch.contains(&new_id_candidate) {
-                continue;
-            }
+    if author.chars().count() + content.chars().count() > MEIGEN_LENGTH_LIMIT {
+        return None;
+    }
 
-            if meigens.iter().any(|x| x.id == new_id_candidate) {
-                continue;
-            }
+    Some((author, content))
+}
 
-            try_fetch.push(new_id_candidate);
-            if try_fetch.len() == want {
-                break;
-            }
+async fn make(
+    db: Synced<impl MeigenDatabase>,
+    author: &str,
+    content: &str,
+    user_id: u64,
+    source: Option<MeigenSource>,
+) -> Result<CommandOutput> {
+    let (author, content) = match prepare_meigen(author, content) {
+        Some(t) => t,
+        None => {
+            return Ok(CommandOutput::error(
+                CommandError::TooLong,
+                "名言が長すぎます。もっと短くしてください。",
+            ))
         }
+    };
 
-        let mut fetched = db.read().await.load_bulk(&try_fetch).await?;
-        meigens.append(&mut fetched);
-    }
-
-    let mut msg = meigens
-        .into_iter()
-        .fold_list()
-        .ok_or_else(|| anyhow!("random::get_random didn't bring any meigen"))?;
+    let meigen = db
+        .write()
+        .await
+        .save(author, content, user_id, source)
+        .await?;
 
-    msg.insert_str(0, clamp_msg);
+    Ok(CommandOutput::meigens("", vec![meigen]))
+}
 
-    Ok(msg)
+/// pages start at 1. 0 is treated as 1.
+fn page_offset(page: Option<u32>, show_count: u8) -> u32 {
+    (page.unwrap_or(1).max(1) - 1) * (show_count as u32)
 }
 
-pub async fn make(db: Synced<impl MeigenDatabase>, author: &str, content: &str) -> Result<String> {
-    let strip = |s: &str| s.replace("`", "");
+fn describe_page(result: &FindResult) -> String {
+    format!(
+        "ページ {}/{} (全{}件)\n",
+        result.page(),
+        result.total_pages(),
+        result.total
+    )
+}
 
-    let author = strip(author);
-    let content = strip(content);
+async fn find(
+    db: Synced<impl MeigenDatabase>,
+    opt: FindOptions<'_>,
+) -> Result<Option<CommandOutput>> {
+    let result = db.read().await.find(opt).await?;
 
-    if author.chars().count() + content.chars().count() > MEIGEN_LENGTH_LIMIT {
-        return Ok("名言が長すぎます。もっと短くしてください。".into());
+    if result.total == 0 {
+        return Ok(None);
     }
 
-    let meigen = db.write().await.save(author, content).await?;
+    let (page, total_pages) = (result.page(), result.total_pages());
 
-    Ok(format!("{}", meigen))
-}
+    let mut output = if result.meigens.is_empty() {
+        CommandOutput::error(
+            CommandError::NotFound,
+            format!(
+                "{}ページ目はありません。全{}件、{}ページです。",
+                page, result.total, total_pages
+            ),
+        )
+    } else {
+        CommandOutput::meigens(describe_page(&result), result.meigens)
+    };
+
+    output.total = result.total;
+    output.page = page;
+    output.total_pages = total_pages;
 
-async fn find(db: Synced<impl MeigenDatabase>, opt: FindOptions<'_>) -> Result<Option<String>> {
-    Ok(db.read().await.find(opt).await?.into_iter().fold_list())
+    Ok(Some(output))
 }
 
-pub async fn search_author(
+async fn search_author(
     db: Synced<impl MeigenDatabase>,
     author: &str,
     show_count: Option<u8>,
     page: Option<u32>,
-) -> Result<String> {
-    let page = page.unwrap_or(0);
-    let (show_count, clamp_msg) = option!({
-        value: show_count,
-        default: 5,
-        min: 1,
-        max: 10
-    });
+) -> Result<CommandOutput> {
+    let (show_count, clamp_msg) = registry::LIST_COUNT.clamp("count", show_count);
 
     find(
         db,
         FindOptions {
             author: Some(author),
             content: None,
-            offset: page * (show_count as u32),
+            sort: SortOrder::Newest,
+            offset: page_offset(page, show_count),
             limit: show_count,
+            ..Default::default()
         },
     )
     .await
-    .edit(|x: &mut String| x.insert_str(0, clamp_msg))
-    .map(|x| x.unwrap_or_else(|| "その条件に合致する名言は見つかりませんでした。".into()))
+    .edit(|x: &mut CommandOutput| x.prepend(&clamp_msg))
+    .map(|x| {
+        x.unwrap_or_else(|| {
+            CommandOutput::message("その条件に合致する名言は見つかりませんでした。")
+        })
+    })
 }
 
-pub async fn search_content(
+async fn search_content(
     db: Synced<impl MeigenDatabase>,
     content: &str,
     show_count: Option<u8>,
     page: Option<u32>,
-) -> Result<String> {
-    let page = page.unwrap_or(0);
-    let (show_count, clamp_msg) = option!({
-        value: show_count,
-        default: 5,
-        min: 1,
-        max: 10
-    });
+) -> Result<CommandOutput> {
+    let (show_count, clamp_msg) = registry::LIST_COUNT.clamp("count", show_count);
 
     find(
         db,
         FindOptions {
             author: None,
             content: Some(content),
-            offset: page * (show_count as u32),
+            sort: SortOrder::Newest,
+            offset: page_offset(page, show_count),
             limit: show_count,
+            ..Default::default()
         },
     )
     .await
-    .edit(|x: &mut String| x.insert_str(0, clamp_msg))
-    .map(|x| x.unwrap_or_else(|| "その条件に合致する名言はみつかりませんでした。".into()))
+    .edit(|x: &mut CommandOutput| x.prepend(&clamp_msg))
+    .map(|x| {
+        x.unwrap_or_else(|| {
+            CommandOutput::message("その条件に合致する名言はみつかりませんでした。")
+        })
+    })
+}
+
+async fn search_query(
+    db: Synced<impl MeigenDatabase>,
+    query: &str,
+    show_count: Option<u8>,
+    page: Option<u32>,
+) -> Result<CommandOutput> {
+    if query.chars().count() > QUERY_LENGTH_LIMIT {
+        return Ok(CommandOutput::error(
+            CommandError::InvalidArgument,
+            format!(
+                "検索クエリが長すぎます。{}文字以内にしてください。",
+                QUERY_LENGTH_LIMIT
+            ),
+        ));
+    }
+
+    let query = match Query::parse(query) {
+        Ok(q) => q,
+        Err(e) => {
+            return Ok(CommandOutput::error(
+                CommandError::InvalidArgument,
+                format!("検索クエリが正しくありません: {}", e),
+            ))
+        }
+    };
+
+    let (show_count, clamp_msg) = registry::LIST_COUNT.clamp("count", show_count);
+
+    find(
+        db,
+        query.find_options(SortOrder::Newest, page_offset(page, show_count), show_count),
+    )
+    .await
+    .edit(|x: &mut CommandOutput| x.prepend(&clamp_msg))
+    .map(|x| {
+        x.unwrap_or_else(|| {
+            CommandOutput::message("その条件に合致する名言はみつかりませんでした。")
+        })
+    })
 }
 
-pub async fn list(
+async fn list(
     db: Synced<impl MeigenDatabase>,
     show_count: Option<u8>,
     page: Option<u32>,
-) -> Result<String> {
-    let page = page.unwrap_or(0);
-    let (show_count, clamp_msg) = option!({
-        value: show_count,
-        default: 5,
-        min: 1,
-        max: 10
-    });
+) -> Result<CommandOutput> {
+    let (show_count, clamp_msg) = registry::LIST_COUNT.clamp("count", show_count);
 
     find(
         db,
         FindOptions {
             author: None,
             content: None,
-            offset: (show_count as u32) * page,
+            sort: SortOrder::Newest,
+            offset: page_offset(page, show_count),
             limit: show_count,
+            ..Default::default()
         },
     )
     .await
-    .edit(|x: &mut String| x.insert_str(0, clamp_msg))
-    .map(|x| x.unwrap_or_else(|| "その条件に合致する名言はみつかりませんでした。".into()))
+    .edit(|x: &mut CommandOutput| x.prepend(&clamp_msg))
+    .map(|x| {
+        x.unwrap_or_else(|| {
+            CommandOutput::message("その条件に合致する名言はみつかりませんでした。")
+        })
+    })
 }
 
-const KAWAEMON_DISCORD_USER_ID: u64 = 391857452360007680;
+async fn ranking(
+    db: Synced<impl MeigenDatabase>,
+    show_count: Option<u8>,
+    page: Option<u32>,
+) -> Result<CommandOutput> {
+    let (show_count, clamp_msg) = registry::LIST_COUNT.clamp("count", show_count);
 
-pub async fn delete(
+    let offset = page_offset(page, show_count);
+
+    let result = db
+        .read()
+        .await
+        .find(FindOptions {
+            author: None,
+            content: None,
+            sort: SortOrder::MostLoved,
+            offset,
+            limit: show_count,
+            ..Default::default()
+        })
+        .await
+        .context("failed to find meigens")?;
+
+    let msg = result
+        .meigens
+        .iter()
+        .enumerate()
+        .map(|(i, m)| format!("{}位 {}", offset as usize + i + 1, m))
+        .fold_ranking();
+
+    let msg = match msg {
+        Some(m) => m,
+        None => {
+            return Ok(CommandOutput::error(
+                CommandError::NotFound,
+                "そのページには名言がありません。",
+            ))
+        }
+    };
+
+    let note = format!("{}{}", clamp_msg, describe_page(&result));
+
+    Ok(CommandOutput {
+        text: format!("{}{}", note, msg),
+        total: result.total,
+        page: result.page(),
+        total_pages: result.total_pages(),
+        first_rank: Some(offset + 1),
+        ..CommandOutput::meigens(note, result.meigens)
+    })
+}
+
+async fn author_ranking(
     db: Synced<impl MeigenDatabase>,
+    show_count: Option<u8>,
+    page: Option<u32>,
+) -> Result<CommandOutput> {
+    let (show_count, clamp_msg) = registry::AUTHOR_RANKING_COUNT.clamp("count", show_count);
+
+    let offset = page_offset(page, show_count);
+
+    let (authors, total) = {
+        let db = db.read().await;
+
+        let authors = db
+            .top_authors(offset, show_count)
+            .await
+            .context("failed to get top authors")?;
+
+        let total = db
+            .count_authors()
+            .await
+            .context("failed to count authors")?;
+
+        (authors, total)
+    };
+
+    let page = offset / show_count as u32 + 1;
+    let total_pages = total.div_ceil(show_count as u32).max(1);
+
+    let msg = authors
+        .iter()
+        .enumerate()
+        .map(|(i, a)| {
+            format!(
+                "{}位 {} (♥ x{}, 名言{}件)",
+                offset as usize + i + 1,
+                a.author,
+                a.loves,
+                a.meigens
+            )
+        })
+        .fold_ranking();
+
+    let mut output = match msg {
+        Some(msg) => CommandOutput::message(format!(
+            "{}ページ {}/{} (全{}人)\n{}",
+            clamp_msg, page, total_pages, total, msg
+        )),
+
+        None if total == 0 => CommandOutput::error(CommandError::NotFound, "まだ作者がいません。"),
+
+        None => CommandOutput::error(
+            CommandError::NotFound,
+            format!(
+                "{}ページ目はありません。全{}人、{}ページです。",
+                page, total, total_pages
+            ),
+        ),
+    };
+
+    output.total = total;
+    output.page = page;
+    output.total_pages = total_pages;
+    output.authors = authors;
+
+    Ok(output)
+}
+
+async fn delete(
+    db: Synced<impl MeigenDatabase>,
+    permissions: &Permissions,
     meigen_id: u32,
-    user_id: u64,
-) -> Result<String> {
-    if user_id != KAWAEMON_DISCORD_USER_ID {
-        return Ok("このコマンドはかわえもんにしか実行できません".into());
+    actor: &Actor,
+) -> Result<CommandOutput> {
+    if !permissions.is_allowed(actor, Action::Delete) {
+        return Ok(CommandOutput::error(
+            CommandError::Forbidden,
+            "このコマンドを実行する権限がありません",
+        ));
     }
 
     let deleted = db
         .write()
         .await
-        .delete(meigen_id)
+        .delete(meigen_id, actor.user_id)
         .await
         .context("failed to delete meigen")?;
 
     Ok(if deleted {
-        "削除しました"
+        CommandOutput::message("削除しました。restoreコマンドで元に戻せます")
     } else {
-        "そのIDを持つ名言は存在しません"
+        CommandOutput::error(CommandError::NotFound, "そのIDを持つ名言は存在しません")
+    })
+}
+
+async fn restore(
+    db: Synced<impl MeigenDatabase>,
+    permissions: &Permissions,
+    meigen_id: u32,
+    actor: &Actor,
+) -> Result<CommandOutput> {
+    if !permissions.is_allowed(actor, Action::Restore) {
+        return Ok(CommandOutput::error(
+            CommandError::Forbidden,
+            "このコマンドを実行する権限がありません",
+        ));
     }
-    .into())
+
+    let restored = db
+        .write()
+        .await
+        .restore(meigen_id, actor.user_id)
+        .await
+        .context("failed to restore meigen")?;
+
+    Ok(if restored {
+        CommandOutput::message("元に戻しました")
+    } else {
+        CommandOutput::error(
+            CommandError::NotFound,
+            "そのIDを持つ削除された名言は存在しません",
+        )
+    })
 }
 
-pub async fn id(db: Synced<impl MeigenDatabase>, id: u32) -> Result<String> {
+async fn audit(
+    db: Synced<impl MeigenDatabase>,
+    permissions: &Permissions,
+    show_count: Option<u8>,
+    actor: &Actor,
+) -> Result<CommandOutput> {
+    if !permissions.is_allowed(actor, Action::ViewAudit) {
+        return Ok(CommandOutput::error(
+            CommandError::Forbidden,
+            "このコマンドを実行する権限がありません",
+        ));
+    }
+
+    let (show_count, clamp_msg) = registry::AUDIT_COUNT.clamp("count", show_count);
+
+    let mut msg = db
+        .read()
+        .await
+        .recent_destructive_changes(show_count)
+        .await
+        .context("failed to get recent destructive changes")?
+        .into_iter()
+        .fold_list()
+        .unwrap_or_else(|| "最近の編集や削除はありません".into());
+
+    msg.insert_str(0, &clamp_msg);
+
+    Ok(CommandOutput::message(msg))
+}
+
+async fn edit(
+    db: Synced<impl MeigenDatabase>,
+    permissions: &Permissions,
+    meigen_id: u32,
+    author: Option<&str>,
+    content: Option<&str>,
+    actor: &Actor,
+) -> Result<CommandOutput> {
+    if author.is_none() && content.is_none() {
+        return Ok(CommandOutput::error(
+            CommandError::InvalidArgument,
+            "authorかcontentのどちらかを指定してください。",
+        ));
+    }
+
+    // hold the write lock from the permission check to the update,
+    // so that the meigen checked is the one updated.
+    let mut db = db.write().await;
+
+    let meigen = db.load(meigen_id).await.context("failed to get meigen")?;
+
+    let meigen = match meigen {
+        Some(m) => m,
+        None => {
+            return Ok(CommandOutput::error(
+                CommandError::NotFound,
+                "そのIDを持つ名言はありません",
+            ))
+        }
+    };
+
+    if !permissions.can_edit(actor, &meigen) {
+        return Ok(CommandOutput::error(
+            CommandError::Forbidden,
+            "名言を登録した本人か管理者しか編集できません",
+        ));
+    }
+
+    let (author, content) = match prepare_meigen(
+        author.unwrap_or(&meigen.author),
+        content.unwrap_or(&meigen.content),
+    ) {
+        Some(t) => t,
+        None => {
+            return Ok(CommandOutput::error(
+                CommandError::TooLong,
+                "名言が長すぎます。もっと短くしてください。",
+            ))
+        }
+    };
+
+    let updated = db
+        .update(meigen_id, author, content, actor.user_id)
+        .await
+        .context("failed to update meigen")?;
+
+    Ok(match updated {
+        Some(m) => CommandOutput::meigens("編集しました。\n", vec![m]),
+        None => CommandOutput::error(CommandError::NotFound, "そのIDを持つ名言はありません"),
+    })
+}
+
+async fn id(db: Synced<impl MeigenDatabase>, id: u32) -> Result<CommandOutput> {
     let meigen = db
         .read()
         .await
@@ -301,12 +793,12 @@ pub async fn id(db: Synced<impl MeigenDatabase>, id: u32) -> Result<String> {
         .context("failed to get meigen")?;
 
     Ok(match meigen {
-        Some(m) => format!("{}", m),
-        None => "そのIDを持つ名言はありません".into(),
+        Some(m) => CommandOutput::meigens("", vec![m]),
+        None => CommandOutput::error(CommandError::NotFound, "そのIDを持つ名言はありません"),
     })
 }
 
-pub async fn gophersay(db: Synced<impl MeigenDatabase>, id: u32) -> Result<String> {
+async fn gophersay(db: Synced<impl MeigenDatabase>, id: u32) -> Result<CommandOutput> {
     let meigen = db
         .read()
         .await
@@ -315,7 +807,12 @@ pub async fn gophersay(db: Synced<impl MeigenDatabase>, id: u32) -> Result<Strin
         .context("failed to get meigen")?;
 
     let meigen = match meigen {
-        None => return Ok("そのIDを持つ名言はありません".into()),
+        None => {
+            return Ok(CommandOutput::error(
+                CommandError::NotFound,
+                "そのIDを持つ名言はありません",
+            ))
+        }
 
         Some(meigen) => format!(
             "{}
@@ -353,10 +850,14 @@ pub async fn gophersay(db: Synced<impl MeigenDatabase>, id: u32) -> Result<Strin
         include_str!("./gopher.ascii")
     );
 
-    Ok(msg)
+    Ok(CommandOutput::message(msg))
 }
 
-pub async fn love(db: Synced<impl MeigenDatabase>, id: u32, from_user_id: u64) -> Result<String> {
+async fn love(
+    db: Synced<impl MeigenDatabase>,
+    id: u32,
+    from_user_id: u64,
+) -> Result<CommandOutput> {
     let meigen = db
         .read()
         .await
@@ -365,25 +866,48 @@ pub async fn love(db: Synced<impl MeigenDatabase>, id: u32, from_user_id: u64) -
         .context("failed to get meigen")?;
 
     if meigen.is_none() {
-        return Ok("名言が見つかりませんでした。".to_owned());
+        return Ok(CommandOutput::error(
+            CommandError::NotFound,
+            "名言が見つかりませんでした。",
+        ));
     }
 
+    let mut db = db.write().await;
+
     let updated = db
-        .write()
-        .await
         .append_loved_user(id, from_user_id)
         .await
         .context("failed to append the loved user id")?;
 
+    // the meigen is attached even if it was already loved, so that the api can return it as is.
+    let meigen = match db.load(id).await.context("failed to get meigen")? {
+        Some(m) => m,
+        None => {
+            return Ok(CommandOutput::error(
+                CommandError::NotFound,
+                "名言が見つかりませんでした。",
+            ))
+        }
+    };
+
     Ok(if updated {
-        "いいねをしました。"
+        CommandOutput::meigens("いいねをしました。\n", vec![meigen])
     } else {
-        "いいねできませんでした。既にいいねをしています。"
-    }
-    .into())
+        CommandOutput {
+            error: Some(CommandError::Conflict),
+            ..CommandOutput::meigens(
+                "いいねできませんでした。既にいいねをしています。\n",
+                vec![meigen],
+            )
+        }
+    })
 }
 
-pub async fn unlove(db: Synced<impl MeigenDatabase>, id: u32, from_user_id: u64) -> Result<String> {
+async fn unlove(
+    db: Synced<impl MeigenDatabase>,
+    id: u32,
+    from_user_id: u64,
+) -> Result<CommandOutput> {
     let meigen = db
         .read()
         .await
@@ -392,20 +916,38 @@ pub async fn unlove(db: Synced<impl MeigenDatabase>, id: u32, from_user_id: u64)
         .context("failed to get meigen")?;
 
     if meigen.is_none() {
-        return Ok("名言が見つかりませんでした。".to_owned());
+        return Ok(CommandOutput::error(
+            CommandError::NotFound,
+            "名言が見つかりませんでした。",
+        ));
     }
 
+    let mut db = db.write().await;
+
     let updated = db
-        .write()
-        .await
         .remove_loved_user(id, from_user_id)
         .await
-        .context("failed to append the loved user id")?;
+        .context("failed to remove the loved user id")?;
+
+    let meigen = match db.load(id).await.context("failed to get meigen")? {
+        Some(m) => m,
+        None => {
+            return Ok(CommandOutput::error(
+                CommandError::NotFound,
+                "名言が見つかりませんでした。",
+            ))
+        }
+    };
 
     Ok(if updated {
-        "いいねを取り消しました。"
+        CommandOutput::meigens("いいねを取り消しました。\n", vec![meigen])
     } else {
-        "いいねを取り消しできませんでした。名言がないか、もともといいねをしていませんでした。"
-    }
-    .into())
+        CommandOutput {
+            error: Some(CommandError::Conflict),
+            ..CommandOutput::meigens(
+                "いいねを取り消しできませんでした。もともといいねをしていませんでした。\n",
+                vec![meigen],
+            )
+        }
+    })
 }
@@ -0,0 +1,610 @@
+//! every command and its options, in one place.
+//! the help text and the console usage are rendered from here, and so is the discord command json
+//! (see entrypoint::discord_webhook::commands). frontends parse their input with `Command::parse`.
+
+use std::str::FromStr;
+
+use super::{Command, SearchBy};
+
+/// the name of the slash command. every command here is its subcommand.
+pub const SLASH_COMMAND_NAME: &str = "gmeigen";
+pub const SLASH_COMMAND_DESCRIPTION: &str = "Create, get or find meigen(s)";
+
+/// the prefix of the console input.
+pub const CONSOLE_PREFIX: &str = "g!meigen";
+
+/// the range of the count options. out of range values are clamped with a message.
+pub struct Limit {
+    pub default: u8,
+    pub min: u8,
+    pub max: u8,
+}
+
+pub const RANDOM_COUNT: Limit = Limit {
+    default: 1,
+    min: 1,
+    max: 5,
+};
+
+pub const LIST_COUNT: Limit = Limit {
+    default: 5,
+    min: 1,
+    max: 10,
+};
+
+pub const AUTHOR_RANKING_COUNT: Limit = Limit {
+    default: 10,
+    min: 1,
+    max: 20,
+};
+
+pub const AUDIT_COUNT: Limit = Limit {
+    default: 10,
+    min: 1,
+    max: 20,
+};
+
+impl Limit {
+    /// clamps number, returns clamped number and message which should be sent to User
+    pub(crate) fn clamp(&self, name: &str, value: Option<u8>) -> (u8, String) {
+        match value.unwrap_or(self.default) {
+            n if n > self.max => (
+                self.max,
+                format!(
+                    "{}の値は大きすぎたため{}に丸められました。\n",
+                    name, self.max
+                ),
+            ),
+
+            n if n < self.min => (
+                self.min,
+                format!(
+                    "{}の値は小さすぎたため{}に丸められました。\n",
+                    name, self.min
+                ),
+            ),
+
+            n => (n, String::new()),
+        }
+    }
+}
+
+pub struct CommandSpec {
+    pub name: &'static str,
+    /// shown in the discord client. must be 1-100 characters.
+    pub description: &'static str,
+    /// shown by the help command.
+    pub help: &'static str,
+    pub options: &'static [OptionSpec],
+    /// non-empty for the groups like `search`. groups can't have options.
+    pub subcommands: &'static [CommandSpec],
+}
+
+pub struct OptionSpec {
+    pub name: &'static str,
+    /// shown in the usage instead of the name.
+    pub label: &'static str,
+    pub description: &'static str,
+    pub ty: OptionType,
+    pub required: bool,
+    pub autocomplete: bool,
+    pub limit: Option<Limit>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OptionType {
+    String,
+    Integer,
+}
+
+const fn text(
+    name: &'static str,
+    label: &'static str,
+    description: &'static str,
+    required: bool,
+) -> OptionSpec {
+    OptionSpec {
+        name,
+        label,
+        description,
+        ty: OptionType::String,
+        required,
+        autocomplete: false,
+        limit: None,
+    }
+}
+
+const fn id(description: &'static str) -> OptionSpec {
+    OptionSpec {
+        name: "id",
+        label: "名言ID",
+        description,
+        ty: OptionType::Integer,
+        required: true,
+        autocomplete: true,
+        limit: None,
+    }
+}
+
+const fn count(description: &'static str, limit: Limit) -> OptionSpec {
+    OptionSpec {
+        name: "count",
+        label: "表示数",
+        description,
+        ty: OptionType::Integer,
+        required: false,
+        autocomplete: false,
+        limit: Some(limit),
+    }
+}
+
+const PAGE: OptionSpec = OptionSpec {
+    name: "page",
+    label: "ページ",
+    description: "page number, starting from 1",
+    ty: OptionType::Integer,
+    required: false,
+    autocomplete: false,
+    limit: None,
+};
+
+const fn command(
+    name: &'static str,
+    description: &'static str,
+    help: &'static str,
+    options: &'static [OptionSpec],
+) -> CommandSpec {
+    CommandSpec {
+        name,
+        description,
+        help,
+        options,
+        subcommands: &[],
+    }
+}
+
+const fn group(
+    name: &'static str,
+    description: &'static str,
+    help: &'static str,
+    subcommands: &'static [CommandSpec],
+) -> CommandSpec {
+    CommandSpec {
+        name,
+        description,
+        help,
+        options: &[],
+        subcommands,
+    }
+}
+
+/// in the order shown to the users.
+pub const COMMANDS: &[CommandSpec] = &[
+    command(
+        "make",
+        "Create a new meigen. Opens a form for multiline meigens if no options are given",
+        "名言を登録します discordでは省略すると入力画面を開きます",
+        &[
+            text("author", "作者", "author of meigen", false),
+            text("content", "名言", "body of meigen", false),
+        ],
+    ),
+    command(
+        "edit",
+        "Edit author or content of meigen. Usable for only the submitter and admins",
+        "名言を編集します 登録した本人と管理者にしか使えません",
+        &[
+            id("MeigenID which you want to edit"),
+            text("author", "作者", "new author of meigen", false),
+            text("content", "名言", "new body of meigen", false),
+        ],
+    ),
+    group(
+        "search",
+        "Search meigens",
+        "名言を検索します",
+        &[
+            command(
+                "author",
+                "Search meigens by author",
+                "作者で名言を検索します",
+                &[
+                    OptionSpec {
+                        autocomplete: true,
+                        ..text("author", "作者", "Search word", true)
+                    },
+                    count("count of shown meigen", LIST_COUNT),
+                    PAGE,
+                ],
+            ),
+            command(
+                "content",
+                "Search meigens by content",
+                "内容で名言を検索します",
+                &[
+                    text("content", "検索語", "Search word", true),
+                    count("count of shown meigen", LIST_COUNT),
+                    PAGE,
+                ],
+            ),
+            command(
+                "query",
+                "Search meigens by query like 'author:foo content:\"bar baz\" loves:>=3 id:100..200 -word'",
+                "条件を組み合わせて検索します 例: author:作者 content:\"名言\" loves:>=3 id:100..200 -除外する語",
+                &[
+                    text("query", "クエリ", "Search query", true),
+                    count("count of shown meigen", LIST_COUNT),
+                    PAGE,
+                ],
+            ),
+        ],
+    ),
+    group(
+        "ranking",
+        "Show the most loved meigens or authors",
+        "いいねのランキングを出します",
+        &[
+            command(
+                "meigen",
+                "Show meigens ordered by loves",
+                "いいねの多い名言を出します",
+                &[count("count of shown meigen", LIST_COUNT), PAGE],
+            ),
+            command(
+                "author",
+                "Show authors ordered by total loves of their meigens",
+                "いいねの合計が多い作者を出します",
+                &[count("count of shown author", AUTHOR_RANKING_COUNT), PAGE],
+            ),
+        ],
+    ),
+    command(
+        "love",
+        "'love' the meigen",
+        "名言にいいねをします",
+        &[id("The id of meigen which you are loving")],
+    ),
+    command(
+        "unlove",
+        "withdraw love for the meigen",
+        "名言のいいねを消します",
+        &[id("The id of meigen which you are no longer loving")],
+    ),
+    command("help", "Show help", "この文を出します", &[]),
+    command(
+        "id",
+        "Show meigen which has specific ID",
+        "指定されたIDの名言を表示します",
+        &[id("MeigenID which you want to get of")],
+    ),
+    command(
+        "gophersay",
+        "Gopher says meigen which has specific ID",
+        "指定されたIDの名言をGopherに言わせます",
+        &[id("MeigenID which you want Gopher to say of")],
+    ),
+    command(
+        "list",
+        "Show list of meigen",
+        "名言をリスト表示します",
+        &[count("count of shown meigen", LIST_COUNT), PAGE],
+    ),
+    command(
+        "random",
+        "Show random meigen",
+        "ランダムに名言を出します",
+        &[count("count of shown meigen", RANDOM_COUNT)],
+    ),
+    command(
+        "status",
+        "Show count of registered meigen",
+        "現在登録されてる名言の数を出します",
+        &[],
+    ),
+    command(
+        "delete",
+        "Delete meigen which has specific ID. Usable for only admins and moderators",
+        "指定されたIDの名言を削除します 管理者とモデレーターにしか使えません",
+        &[id("MeigenID which you want to delete of")],
+    ),
+    command(
+        "restore",
+        "Restore deleted meigen which has specific ID. Usable for only admins and moderators",
+        "削除された名言を元に戻します 管理者とモデレーターにしか使えません",
+        &[id("MeigenID which you want to restore of")],
+    ),
+    command(
+        "audit",
+        "Show recent edits and deletes. Usable for only admins",
+        "最近の編集と削除を表示します 管理者にしか使えません",
+        &[count("How many changes to show", AUDIT_COUNT)],
+    ),
+];
+
+/// how the user writes the commands. the help text differs between them.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    /// `/gmeigen search author author:foo`
+    Slash,
+    /// `g!meigen search author foo`
+    Console,
+}
+
+/// finds the command by the leading words, e.g. `["search", "author", "foo"]`.
+/// returns the command and the number of words used for its name.
+/// the command is a group if the words end before its subcommand.
+pub fn find(words: &[&str]) -> Option<(&'static CommandSpec, usize)> {
+    let mut specs = COMMANDS;
+    let mut found = None;
+
+    for (i, word) in words.iter().enumerate() {
+        let spec = specs.iter().find(|x| x.name == *word)?;
+        found = Some((spec, i + 1));
+
+        if spec.subcommands.is_empty() {
+            break;
+        }
+
+        specs = spec.subcommands;
+    }
+
+    found
+}
+
+impl CommandSpec {
+    /// the index of the option which takes the rest of the console arguments, so that it can contain spaces.
+    /// it is the last text option. the options after it can't be given on the console.
+    fn rest_option(&self) -> Option<usize> {
+        self.options
+            .iter()
+            .rposition(|x| x.ty == OptionType::String)
+    }
+
+    /// assigns the console arguments to the options in order. `-` leaves an optional option unset.
+    /// the rest option takes the words up to the ones which fill the options after it.
+    pub fn console_args(&self, args: &[&str]) -> Vec<(&'static str, String)> {
+        let rest = self.rest_option();
+        let mut assigned = vec![];
+        let mut args = args;
+
+        for (i, option) in self.options.iter().enumerate() {
+            let value = if Some(i) == rest {
+                let trailing = trailing_args(&self.options[i + 1..], args);
+                let (words, others) = args.split_at(args.len() - trailing);
+                args = others;
+                words.join(" ")
+            } else {
+                match args.split_first() {
+                    Some((x, others)) => {
+                        args = others;
+                        x.to_string()
+                    }
+                    None => break,
+                }
+            };
+
+            if !value.is_empty() && value != "-" {
+                assigned.push((option.name, value));
+            }
+        }
+
+        assigned
+    }
+
+    /// one line for each command. groups are expanded into their subcommands.
+    fn usage_lines(&self, parent: &str, syntax: Syntax, lines: &mut Vec<(String, &'static str)>) {
+        let name = format!("{}{}", parent, self.name);
+
+        if !self.subcommands.is_empty() {
+            for sub in self.subcommands {
+                sub.usage_lines(&format!("{} ", name), syntax, lines);
+            }
+
+            return;
+        }
+
+        let rest = self.rest_option();
+        let mut usage = name;
+
+        for (i, option) in self.options.iter().enumerate() {
+            let default = match option.limit {
+                Some(ref l) => format!("={}", l.default),
+                None => String::new(),
+            };
+
+            let optional = if option.required || !default.is_empty() {
+                ""
+            } else {
+                "?"
+            };
+
+            match syntax {
+                Syntax::Slash => usage.push_str(&format!(
+                    " [{}:{}{}{}]",
+                    option.name, option.label, default, optional
+                )),
+
+                Syntax::Console => {
+                    let spread = if Some(i) == rest { "..." } else { "" };
+
+                    usage.push_str(&format!(
+                        " [{}{}{}{}]",
+                        option.label, default, optional, spread
+                    ));
+                }
+            }
+        }
+
+        lines.push((usage, self.help));
+    }
+
+    /// the usage of this command, to be shown when the console input was wrong.
+    pub fn usage(&self, syntax: Syntax) -> String {
+        let mut lines = vec![];
+        self.usage_lines(&format!("{} ", prefix(syntax)), syntax, &mut lines);
+        render_lines(&lines)
+    }
+}
+
+/// how many words at the end of `args` are for `options`, which come after the rest option.
+/// they are taken only while they fit the type of the option, and at least one word is left for the rest.
+fn trailing_args(options: &[OptionSpec], args: &[&str]) -> usize {
+    let fits = |option: &OptionSpec, word: &&str| match option.ty {
+        OptionType::Integer => *word == "-" || word.parse::<i64>().is_ok(),
+        OptionType::String => true,
+    };
+
+    let max = options.len().min(args.len().saturating_sub(1));
+
+    (0..=max)
+        .rev()
+        .find(|&n| {
+            args[args.len() - n..]
+                .iter()
+                .zip(options)
+                .all(|(word, option)| fits(option, word))
+        })
+        .unwrap_or(0)
+}
+
+fn prefix(syntax: Syntax) -> String {
+    match syntax {
+        Syntax::Slash => format!("/{}", SLASH_COMMAND_NAME),
+        Syntax::Console => CONSOLE_PREFIX.to_string(),
+    }
+}
+
+// full width characters take two columns.
+fn display_width(text: &str) -> usize {
+    text.chars().map(|x| if x.is_ascii() { 1 } else { 2 }).sum()
+}
+
+fn render_lines(lines: &[(String, &str)]) -> String {
+    let width = lines
+        .iter()
+        .map(|(u, _)| display_width(u))
+        .max()
+        .unwrap_or(0);
+
+    lines
+        .iter()
+        .map(|(usage, help)| {
+            let padding = " ".repeat(width - display_width(usage));
+            format!("    {}{} :: {}\n", usage, padding, help)
+        })
+        .collect()
+}
+
+/// the text shown by the help command.
+pub fn help_text(syntax: Syntax) -> String {
+    let mut lines = vec![];
+
+    for spec in COMMANDS {
+        spec.usage_lines("", syntax, &mut lines);
+    }
+
+    let note = match syntax {
+        Syntax::Slash => "[subcommand] [option:説明...]",
+        Syntax::Console => "[subcommand] [args...] (省略する引数には - を書きます)",
+    };
+
+    format!(
+        "```asciidoc
+= meigen-bot-rust =
+{} {}
+= subcommands =
+{}```",
+        prefix(syntax),
+        note,
+        render_lines(&lines)
+    )
+}
+
+/// why the input could not be parsed into a Command.
+#[derive(Debug)]
+pub enum ParseError {
+    UnknownCommand,
+    /// a required option is missing.
+    Missing(&'static str),
+    /// the message shown to the user.
+    Invalid(String),
+}
+
+fn number<T: FromStr>(name: &'static str, value: Option<&str>) -> Result<Option<T>, ParseError> {
+    value
+        .map(|x| x.parse::<T>())
+        .transpose()
+        .map_err(|_| {
+            ParseError::Invalid(format!(
+                "{}フィールド({})のパースに失敗しました。もしかしたら数字が大きすぎるとか小さすぎるとかマイナスだからとかかもしれません。",
+                name,
+                std::any::type_name::<T>()
+            ))
+        })
+}
+
+impl Command {
+    /// builds the command from its name, e.g. `["search", "author"]`, and the values of its options.
+    pub fn parse<'a>(
+        path: &[&str],
+        syntax: Syntax,
+        get: impl Fn(&str) -> Option<&'a str>,
+    ) -> Result<Self, ParseError> {
+        let text = |name: &'static str| get(name).ok_or(ParseError::Missing(name));
+        let id = || number("id", get("id"))?.ok_or(ParseError::Missing("id"));
+        let count = || number("count", get("count"));
+        let page = || number("page", get("page"));
+
+        let search = |by: fn(String) -> SearchBy, name: &'static str| {
+            Ok(Command::Search {
+                by: by(text(name)?.to_string()),
+                count: count()?,
+                page: page()?,
+            })
+        };
+
+        match path {
+            ["make"] => match (get("author"), get("content")) {
+                (Some(author), Some(content)) => Ok(Command::Make {
+                    author: author.to_string(),
+                    content: content.to_string(),
+                    source: None,
+                }),
+                _ => Err(ParseError::Invalid(
+                    "authorとcontentの両方を指定してください。".into(),
+                )),
+            },
+            ["edit"] => Ok(Command::Edit {
+                id: id()?,
+                author: get("author").map(str::to_string),
+                content: get("content").map(str::to_string),
+            }),
+            ["search", "author"] => search(SearchBy::Author, "author"),
+            ["search", "content"] => search(SearchBy::Content, "content"),
+            ["search", "query"] => search(SearchBy::Query, "query"),
+            ["ranking", "meigen"] => Ok(Command::Ranking {
+                count: count()?,
+                page: page()?,
+            }),
+            ["ranking", "author"] => Ok(Command::AuthorRanking {
+                count: count()?,
+                page: page()?,
+            }),
+            ["love"] => Ok(Command::Love(id()?)),
+            ["unlove"] => Ok(Command::Unlove(id()?)),
+            ["help"] => Ok(Command::Help(syntax)),
+            ["id"] => Ok(Command::Id(id()?)),
+            ["gophersay"] => Ok(Command::Gophersay(id()?)),
+            ["list"] => Ok(Command::List {
+                count: count()?,
+                page: page()?,
+            }),
+            ["random"] => Ok(Command::Random { count: count()? }),
+            ["status"] => Ok(Command::Status),
+            ["delete"] => Ok(Command::Delete(id()?)),
+            ["restore"] => Ok(Command::Restore(id()?)),
+            ["audit"] => Ok(Command::Audit { count: count()? }),
+            _ => Err(ParseError::UnknownCommand),
+        }
+    }
+}
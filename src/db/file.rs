@@ -0,0 +1,264 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use tokio::{fs::File, io::AsyncWriteExt};
+
+use crate::{
+    db::{mem::MemoryMeigenDatabase, FindOptions, FindResult, MeigenDatabase},
+    model::{AuthorStats, HistoryEntry, Meigen, MeigenSource},
+};
+
+/// keeps all meigens on memory like `MemoryMeigenDatabase` does,
+/// and writes them into a local json file every time they are modified.
+pub struct FileMeigenDatabase {
+    path: PathBuf,
+    inner: MemoryMeigenDatabase,
+}
+
+impl FileMeigenDatabase {
+    pub async fn new(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let inner = read(&path).await?;
+
+        Ok(Self { path, inner })
+    }
+
+    async fn flush(&self) -> Result<()> {
+        let json = serde_json::to_vec(&self.inner).context("failed to serialize meigens")?;
+
+        // write into another file first, so that the crash while writing doesn't break the database.
+        // it's synced before the rename, or the rename can reach the disk before the content does.
+        let temp_path = self.path.with_extension("tmp");
+
+        let mut file = File::create(&temp_path)
+            .await
+            .with_context(|| format!("failed to create {}", temp_path.display()))?;
+
+        file.write_all(&json)
+            .await
+            .with_context(|| format!("failed to write {}", temp_path.display()))?;
+
+        file.sync_all()
+            .await
+            .with_context(|| format!("failed to sync {}", temp_path.display()))?;
+
+        tokio::fs::rename(&temp_path, &self.path)
+            .await
+            .with_context(|| format!("failed to replace {}", self.path.display()))
+    }
+
+    /// writes the modified memory. if it failed, the memory is read back from the file,
+    /// so that it doesn't have the changes which are not in the file.
+    async fn flush_if(&mut self, modified: bool) -> Result<bool> {
+        if !modified {
+            return Ok(false);
+        }
+
+        if let Err(e) = self.flush().await {
+            self.inner = read(&self.path)
+                .await
+                .context("failed to roll back after the failed write")?;
+
+            return Err(e);
+        }
+
+        Ok(true)
+    }
+}
+
+async fn read(path: &Path) -> Result<MemoryMeigenDatabase> {
+    match tokio::fs::read(path).await {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .with_context(|| format!("failed to deserialize {}", path.display())),
+
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            tracing::info!(
+                "{} was not found. starting with empty database",
+                path.display()
+            );
+            Ok(MemoryMeigenDatabase::new())
+        }
+
+        Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
+
+#[async_trait]
+impl MeigenDatabase for FileMeigenDatabase {
+    async fn save(
+        &mut self,
+        author: String,
+        content: String,
+        created_by: u64,
+        source: Option<MeigenSource>,
+    ) -> Result<Meigen> {
+        let meigen = self.inner.save(author, content, created_by, source).await?;
+        self.flush_if(true).await?;
+
+        Ok(meigen)
+    }
+
+    async fn load(&self, id: u32) -> Result<Option<Meigen>> {
+        self.inner.load(id).await
+    }
+
+    async fn load_bulk(&self, id: &[u32]) -> Result<Vec<Meigen>> {
+        self.inner.load_bulk(id).await
+    }
+
+    async fn load_deleted_bulk(&self, id: &[u32]) -> Result<Vec<Meigen>> {
+        self.inner.load_deleted_bulk(id).await
+    }
+
+    async fn delete(&mut self, id: u32, deleted_by: u64) -> Result<bool> {
+        let deleted = self.inner.delete(id, deleted_by).await?;
+        self.flush_if(deleted).await
+    }
+
+    async fn restore(&mut self, id: u32, restored_by: u64) -> Result<bool> {
+        let restored = self.inner.restore(id, restored_by).await?;
+        self.flush_if(restored).await
+    }
+
+    async fn update(
+        &mut self,
+        id: u32,
+        author: String,
+        content: String,
+        edited_by: u64,
+    ) -> Result<Option<Meigen>> {
+        let updated = self.inner.update(id, author, content, edited_by).await?;
+        self.flush_if(updated.is_some()).await?;
+
+        Ok(updated)
+    }
+
+    async fn history(&self, id: u32) -> Result<Vec<HistoryEntry>> {
+        self.inner.history(id).await
+    }
+
+    async fn recent_destructive_changes(&self, limit: u8) -> Result<Vec<HistoryEntry>> {
+        self.inner.recent_destructive_changes(limit).await
+    }
+
+    async fn get_current_id(&self) -> Result<u32> {
+        self.inner.get_current_id().await
+    }
+
+    async fn find(&self, options: FindOptions<'_>) -> Result<FindResult> {
+        self.inner.find(options).await
+    }
+
+    async fn count(&self) -> Result<u32> {
+        self.inner.count().await
+    }
+
+    async fn top_authors(&self, offset: u32, limit: u8) -> Result<Vec<AuthorStats>> {
+        self.inner.top_authors(offset, limit).await
+    }
+
+    async fn count_authors(&self) -> Result<u32> {
+        self.inner.count_authors().await
+    }
+
+    async fn authors_by_prefix(&self, prefix: &str, limit: u8) -> Result<Vec<String>> {
+        self.inner.authors_by_prefix(prefix, limit).await
+    }
+
+    async fn sample(&self, count: u32) -> Result<Vec<Meigen>> {
+        self.inner.sample(count).await
+    }
+
+    async fn append_loved_user(&mut self, id: u32, loved_user_id: u64) -> Result<bool> {
+        let appended = self.inner.append_loved_user(id, loved_user_id).await?;
+        self.flush_if(appended).await
+    }
+
+    async fn remove_loved_user(&mut self, id: u32, loved_user_id: u64) -> Result<bool> {
+        let removed = self.inner.remove_loved_user(id, loved_user_id).await?;
+        self.flush_if(removed).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::model::HistoryKind;
+
+    #[tokio::test]
+    async fn changes_survive_reopening() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("meigens.json");
+
+        {
+            let mut db = FileMeigenDatabase::new(&path).await.unwrap();
+            db.save("a".into(), "first".into(), 10, None).await.unwrap();
+            db.save("b".into(), "second".into(), 10, None)
+                .await
+                .unwrap();
+            db.save("c".into(), "third".into(), 10, None).await.unwrap();
+
+            assert!(db.append_loved_user(1, 20).await.unwrap());
+            assert!(db
+                .update(2, "b".into(), "edited".into(), 30)
+                .await
+                .unwrap()
+                .is_some());
+            assert!(db.delete(3, 40).await.unwrap());
+        }
+
+        let db = FileMeigenDatabase::new(&path).await.unwrap();
+
+        let first = db.load(1).await.unwrap().unwrap();
+        assert_eq!(first.content, "first");
+        assert_eq!(first.loved_user_id, vec![20]);
+        assert_eq!(first.created_by, Some(10));
+
+        let second = db.load(2).await.unwrap().unwrap();
+        assert_eq!(second.content, "edited");
+
+        assert!(db.load(3).await.unwrap().is_none());
+        assert_eq!(db.load_deleted_bulk(&[3]).await.unwrap().len(), 1);
+        assert_eq!(db.get_current_id().await.unwrap(), 3);
+        assert_eq!(db.count().await.unwrap(), 2);
+
+        let history = db.history(2).await.unwrap();
+        assert!(matches!(
+            history.last().unwrap().kind,
+            HistoryKind::Edit { .. }
+        ));
+
+        // the temporary file is renamed into the database.
+        assert!(!path.with_extension("tmp").exists());
+    }
+
+    #[tokio::test]
+    async fn loads_file_written_before_history_and_trash() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("meigens.json");
+
+        std::fs::write(
+            &path,
+            r#"{"meigens":[{"id":1,"author":"a","content":"b","loved_user_id":[5]}]}"#,
+        )
+        .unwrap();
+
+        let mut db = FileMeigenDatabase::new(&path).await.unwrap();
+
+        let meigen = db.load(1).await.unwrap().unwrap();
+        assert_eq!(meigen.author, "a");
+        assert_eq!(meigen.loved_user_id, vec![5]);
+        assert_eq!(meigen.created_at, None);
+        assert_eq!(db.get_current_id().await.unwrap(), 1);
+        assert!(db.history(1).await.unwrap().is_empty());
+
+        let saved = db.save("c".into(), "d".into(), 10, None).await.unwrap();
+        assert_eq!(saved.id, 2);
+    }
+}
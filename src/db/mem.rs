@@ -1,39 +1,107 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+};
+
 use anyhow::Result;
 use async_trait::async_trait;
+use chrono::Utc;
+use rand::{prelude::SmallRng, seq::IteratorRandom, SeedableRng};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    db::{FindOptions, MeigenDatabase},
-    model::Meigen,
+    db::{FindOptions, FindResult, MeigenDatabase, NotEnoughMeigens, SortOrder},
+    model::{AuthorStats, HistoryEntry, HistoryKind, Meigen, MeigenSource},
+    normalize::normalize,
 };
 
-#[derive(Default)]
+const HISTORY_LIMIT: usize = 10000;
+
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct MemoryMeigenDatabase {
+    #[serde(rename = "meigens")]
     inner: Vec<Meigen>,
+
+    // the largest id ever allocated. kept apart from `inner` so that deleted ids are never reused.
+    #[serde(default)]
+    last_id: u32,
+
+    // the oldest ones are dropped beyond HISTORY_LIMIT, as all of them are kept on memory.
+    #[serde(default)]
+    history: VecDeque<HistoryEntry>,
+
+    // deleted meigens, waiting to be restored.
+    #[serde(default)]
+    trash: Vec<Meigen>,
 }
 
 impl MemoryMeigenDatabase {
     pub fn new() -> Self {
-        Self { inner: vec![] }
+        Self {
+            inner: vec![],
+            last_id: 0,
+            history: VecDeque::new(),
+            trash: vec![],
+        }
+    }
+
+    fn record(&mut self, meigen_id: u32, kind: HistoryKind, actor: u64) {
+        self.push_history(HistoryEntry {
+            meigen_id,
+            kind,
+            actor,
+            at: Utc::now(),
+        });
+    }
+
+    fn push_history(&mut self, entry: HistoryEntry) {
+        while self.history.len() >= HISTORY_LIMIT {
+            self.history.pop_front();
+        }
+
+        self.history.push_back(entry);
     }
 }
 
 #[async_trait]
 impl MeigenDatabase for MemoryMeigenDatabase {
     async fn get_current_id(&self) -> Result<u32> {
-        Ok(self.inner.iter().map(|x| x.id).max().unwrap_or(0))
+        // last_id is missing in the files written by FileMeigenDatabase before it was introduced.
+        let max_id = self
+            .inner
+            .iter()
+            .chain(self.trash.iter())
+            .map(|x| x.id)
+            .max()
+            .unwrap_or(0);
+        Ok(self.last_id.max(max_id))
     }
 
-    async fn save(&mut self, author: String, content: String) -> Result<Meigen> {
-        let id = self.get_current_id().await?;
+    async fn save(
+        &mut self,
+        author: String,
+        content: String,
+        created_by: u64,
+        source: Option<MeigenSource>,
+    ) -> Result<Meigen> {
+        let id = self.get_current_id().await? + 1;
+        self.last_id = id;
+
+        let now = Utc::now();
 
         let meigen = Meigen {
-            id: id + 1,
+            id,
             author,
             content,
             loved_user_id: Vec::new(),
+            created_at: Some(now),
+            created_by: Some(created_by),
+            updated_at: Some(now),
+            source,
         };
 
         self.inner.push(meigen.clone());
+        self.record(id, HistoryKind::Create, created_by);
 
         Ok(meigen)
     }
@@ -46,54 +114,283 @@ impl MeigenDatabase for MemoryMeigenDatabase {
         Ok(self
             .inner
             .iter()
-            .filter(|x| id.iter().any(|&y| y == x.id))
+            .filter(|x| id.contains(&x.id))
+            .cloned()
+            .collect())
+    }
+
+    async fn load_deleted_bulk(&self, id: &[u32]) -> Result<Vec<Meigen>> {
+        Ok(self
+            .trash
+            .iter()
+            .filter(|x| id.contains(&x.id))
             .cloned()
             .collect())
     }
 
-    async fn delete(&mut self, id: u32) -> Result<bool> {
-        let pos = self.inner.iter().position(|x| x.id == id);
+    async fn delete(&mut self, id: u32, deleted_by: u64) -> Result<bool> {
+        let pos = match self.inner.iter().position(|x| x.id == id) {
+            Some(p) => p,
+            None => return Ok(false),
+        };
 
-        Ok(match pos {
-            Some(pos) => {
-                self.inner.remove(pos);
-                true
-            }
+        let meigen = self.inner.remove(pos);
+        self.trash.push(meigen);
+        self.record(id, HistoryKind::Delete, deleted_by);
 
-            None => false,
-        })
+        Ok(true)
     }
 
-    async fn find(&self, options: FindOptions<'_>) -> Result<Vec<Meigen>> {
+    async fn restore(&mut self, id: u32, restored_by: u64) -> Result<bool> {
+        let pos = match self.trash.iter().position(|x| x.id == id) {
+            Some(p) => p,
+            None => return Ok(false),
+        };
+
+        let meigen = self.trash.remove(pos);
+
+        // keep inner sorted by id, as find relies on the order.
+        let insert_at = self
+            .inner
+            .iter()
+            .position(|x| x.id > id)
+            .unwrap_or(self.inner.len());
+        self.inner.insert(insert_at, meigen);
+
+        self.record(id, HistoryKind::Restore, restored_by);
+
+        Ok(true)
+    }
+
+    async fn update(
+        &mut self,
+        id: u32,
+        author: String,
+        content: String,
+        edited_by: u64,
+    ) -> Result<Option<Meigen>> {
+        let meigen = match self.inner.iter_mut().find(|x| x.id == id) {
+            Some(m) => m,
+            None => return Ok(None),
+        };
+
+        let now = Utc::now();
+
+        let previous_author = std::mem::replace(&mut meigen.author, author);
+        let previous_content = std::mem::replace(&mut meigen.content, content);
+        meigen.updated_at = Some(now);
+
+        let meigen = meigen.clone();
+
+        self.push_history(HistoryEntry {
+            meigen_id: id,
+            kind: HistoryKind::Edit {
+                previous_author,
+                previous_content,
+            },
+            actor: edited_by,
+            at: now,
+        });
+
+        Ok(Some(meigen))
+    }
+
+    async fn history(&self, id: u32) -> Result<Vec<HistoryEntry>> {
         Ok(self
+            .history
+            .iter()
+            .filter(|x| x.meigen_id == id)
+            .cloned()
+            .collect())
+    }
+
+    async fn recent_destructive_changes(&self, limit: u8) -> Result<Vec<HistoryEntry>> {
+        Ok(self
+            .history
+            .iter()
+            .rev()
+            .filter(|x| x.kind.is_destructive())
+            .take(limit as _)
+            .cloned()
+            .collect())
+    }
+
+    async fn find(&self, options: FindOptions<'_>) -> Result<FindResult> {
+        anyhow::ensure!(
+            options.sort == SortOrder::Newest || !options.has_cursor(),
+            "cursors are supported only with SortOrder::Newest"
+        );
+
+        let query_author = options.author.map(normalize);
+        let query_content = options.content.map(normalize);
+        let keywords = options
+            .keywords
+            .iter()
+            .map(|x| normalize(x))
+            .collect::<Vec<_>>();
+        let excludes = options
+            .excludes
+            .iter()
+            .map(|x| normalize(x))
+            .collect::<Vec<_>>();
+
+        let mut found = self
             .inner
             .iter()
             .rev()
             .flat_map(|x| {
-                if let Some(author) = options.author {
-                    if !x.author.contains(author) {
+                if !options.loves.contains(x.loves() as u32) || !options.id.contains(x.id) {
+                    return None;
+                }
+
+                // there is no index on memory. normalize them every time instead.
+                let author = normalize(&x.author);
+                let content = normalize(&x.content);
+
+                if let Some(ref query_author) = query_author {
+                    if !author.contains(query_author.as_str()) {
                         return None;
                     }
                 }
 
-                if let Some(content) = options.content {
-                    if !x.content.contains(content) {
+                if let Some(ref query_content) = query_content {
+                    if !content.contains(query_content.as_str()) {
                         return None;
                     }
                 }
 
+                let contains = |word: &String| {
+                    author.contains(word.as_str()) || content.contains(word.as_str())
+                };
+
+                if !keywords.iter().all(contains) {
+                    return None;
+                }
+
+                if excludes.iter().any(contains) {
+                    return None;
+                }
+
                 Some(x)
             })
-            .skip(options.offset as _)
-            .take(options.limit as _)
-            .cloned()
-            .collect())
+            .collect::<Vec<_>>();
+
+        if options.sort == SortOrder::MostLoved {
+            // the sort is stable, so newer ones come first among the same loves.
+            found.sort_by_key(|x| Reverse(x.loves()));
+        }
+
+        let total = found.len();
+        let offset = options.offset as usize;
+        let limit = options.limit as usize;
+
+        // found is sorted by id descending if the cursor is given.
+        let count_larger = |id: u32| found.iter().take_while(|x| x.id > id).count();
+
+        let (start, end) = match (options.newer_than, options.older_than) {
+            (None, None) => (offset, offset + limit),
+
+            (newer_than, Some(older_than)) => {
+                let start = count_larger(older_than.saturating_sub(1)) + offset;
+                let end = newer_than.map_or(total, count_larger).min(start + limit);
+                (start, end)
+            }
+
+            (Some(newer_than), None) => {
+                let end = count_larger(newer_than).saturating_sub(offset);
+                (end.saturating_sub(limit), end)
+            }
+        };
+
+        let end = end.min(total);
+        let start = start.min(end);
+
+        Ok(FindResult {
+            total: total as _,
+            meigens: found.drain(start..end).cloned().collect(),
+            preceding: start as _,
+            limit: options.limit,
+        })
     }
 
     async fn count(&self) -> Result<u32> {
         Ok(self.inner.len() as _)
     }
 
+    async fn top_authors(&self, offset: u32, limit: u8) -> Result<Vec<AuthorStats>> {
+        let mut stats = HashMap::<&str, AuthorStats>::new();
+
+        for meigen in &self.inner {
+            let entry = stats
+                .entry(meigen.author.as_str())
+                .or_insert_with(|| AuthorStats {
+                    author: meigen.author.clone(),
+                    loves: 0,
+                    meigens: 0,
+                });
+
+            entry.loves += meigen.loves() as u32;
+            entry.meigens += 1;
+        }
+
+        let mut stats = stats.into_values().collect::<Vec<_>>();
+        stats.sort_by(|a, b| b.loves.cmp(&a.loves).then_with(|| a.author.cmp(&b.author)));
+
+        Ok(stats
+            .into_iter()
+            .skip(offset as _)
+            .take(limit as _)
+            .collect())
+    }
+
+    async fn count_authors(&self) -> Result<u32> {
+        let authors = self
+            .inner
+            .iter()
+            .map(|x| x.author.as_str())
+            .collect::<HashSet<_>>();
+
+        Ok(authors.len() as u32)
+    }
+
+    async fn authors_by_prefix(&self, prefix: &str, limit: u8) -> Result<Vec<String>> {
+        let prefix = normalize(prefix);
+
+        let authors = self
+            .inner
+            .iter()
+            .map(|x| x.author.as_str())
+            .filter(|x| normalize(x).starts_with(prefix.as_str()))
+            .collect::<BTreeSet<_>>();
+
+        Ok(authors
+            .into_iter()
+            .take(limit as _)
+            .map(ToOwned::to_owned)
+            .collect())
+    }
+
+    async fn sample(&self, count: u32) -> Result<Vec<Meigen>> {
+        if self.inner.len() < count as usize {
+            return Err(NotEnoughMeigens {
+                requested: count,
+                available: self.inner.len() as _,
+            }
+            .into());
+        }
+
+        let mut rng = SmallRng::from_rng(&mut rand::thread_rng()).unwrap();
+
+        // choose_multiple does reservoir sampling, so gaps between ids don't matter.
+        Ok(self
+            .inner
+            .iter()
+            .choose_multiple(&mut rng, count as _)
+            .into_iter()
+            .cloned()
+            .collect())
+    }
+
     async fn append_loved_user(&mut self, id: u32, loved_user_id: u64) -> Result<bool> {
         let meigen = match self.inner.iter_mut().find(|x| x.id == id) {
             Some(m) => m,
@@ -105,6 +402,7 @@ impl MeigenDatabase for MemoryMeigenDatabase {
         }
 
         meigen.loved_user_id.push(loved_user_id);
+        self.record(id, HistoryKind::Love, loved_user_id);
 
         Ok(true)
     }
@@ -123,9 +421,69 @@ impl MeigenDatabase for MemoryMeigenDatabase {
         match pos {
             Some(p) => {
                 meigen.loved_user_id.swap_remove(p);
+                self.record(id, HistoryKind::Unlove, loved_user_id);
                 Ok(true)
             }
             None => Ok(false),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 1 to 7, and 4 is deleted.
+    async fn with_gap() -> MemoryMeigenDatabase {
+        let mut db = MemoryMeigenDatabase::new();
+
+        for i in 1..=7 {
+            db.save("a".into(), format!("meigen {}", i), 1, None)
+                .await
+                .unwrap();
+        }
+
+        assert!(db.delete(4, 1).await.unwrap());
+        db
+    }
+
+    async fn page(
+        db: &MemoryMeigenDatabase,
+        older_than: Option<u32>,
+        newer_than: Option<u32>,
+    ) -> (Vec<u32>, u32, u32) {
+        let result = db
+            .find(FindOptions {
+                older_than,
+                newer_than,
+                limit: 2,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let ids = result.meigens.iter().map(|x| x.id).collect();
+        (ids, result.preceding, result.total)
+    }
+
+    #[tokio::test]
+    async fn cursors_walk_over_deleted_id() {
+        let db = with_gap().await;
+
+        // forward, from the newest one.
+        assert_eq!(page(&db, None, None).await, (vec![7, 6], 0, 6));
+        assert_eq!(page(&db, Some(6), None).await, (vec![5, 3], 2, 6));
+        assert_eq!(page(&db, Some(3), None).await, (vec![2, 1], 4, 6));
+        assert_eq!(page(&db, Some(1), None).await, (vec![], 6, 6));
+
+        // backward, from the oldest page.
+        assert_eq!(page(&db, None, Some(2)).await, (vec![5, 3], 2, 6));
+        assert_eq!(page(&db, None, Some(5)).await, (vec![7, 6], 0, 6));
+        assert_eq!(page(&db, None, Some(7)).await, (vec![], 0, 6));
+
+        // the cursor can be the deleted id itself, e.g. an old link.
+        assert_eq!(page(&db, Some(4), None).await, (vec![3, 2], 3, 6));
+        assert_eq!(page(&db, None, Some(4)).await, (vec![6, 5], 1, 6));
+        assert_eq!(page(&db, Some(7), Some(3)).await, (vec![6, 5], 1, 6));
+    }
+}
@@ -1,4 +1,6 @@
-#[cfg(feature = "memorydb")]
+#[cfg(feature = "filedb")]
+pub mod file;
+#[cfg(any(feature = "memorydb", feature = "filedb"))]
 pub mod mem;
 #[cfg(feature = "mongodb_")]
 pub mod mongo;
@@ -6,29 +8,190 @@ pub mod mongo;
 use anyhow::Result;
 use async_trait::async_trait;
 
-use crate::model::Meigen;
+use crate::model::{AuthorStats, HistoryEntry, Meigen, MeigenSource};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortOrder {
+    /// larger id first.
+    #[default]
+    Newest,
+    /// more loves first. meigens with the same loves are sorted by `Newest`.
+    MostLoved,
+}
+
+/// inclusive range of numbers. None means unbounded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Bounds {
+    pub min: Option<u32>,
+    pub max: Option<u32>,
+}
+
+impl Bounds {
+    pub fn is_unbounded(&self) -> bool {
+        self.min.is_none() && self.max.is_none()
+    }
+
+    pub fn contains(&self, value: u32) -> bool {
+        let above_min = match self.min {
+            Some(min) => min <= value,
+            None => true,
+        };
+
+        let below_max = match self.max {
+            Some(max) => value <= max,
+            None => true,
+        };
+
+        above_min && below_max
+    }
+
+    /// the range which satisfies both of them.
+    pub fn intersect(self, other: Bounds) -> Bounds {
+        let pick = |a: Option<u32>, b: Option<u32>, f: fn(u32, u32) -> u32| match (a, b) {
+            (Some(a), Some(b)) => Some(f(a, b)),
+            (a, b) => a.or(b),
+        };
+
+        Bounds {
+            min: pick(self.min, other.min, std::cmp::max),
+            max: pick(self.max, other.max, std::cmp::min),
+        }
+    }
+}
 
 #[derive(Default)]
 pub struct FindOptions<'a> {
     pub author: Option<&'a str>,
     pub content: Option<&'a str>,
+    /// each of them must be contained in either author or content.
+    pub keywords: Vec<&'a str>,
+    /// none of them may be contained in author and content.
+    pub excludes: Vec<&'a str>,
+    pub loves: Bounds,
+    pub id: Bounds,
+    pub sort: SortOrder,
+
+    // keyset pagination, which stays stable when meigens are registered between pages.
+    // only meigens with smaller id than `older_than` (and larger id than `newer_than`) are returned.
+    // when only `newer_than` is given, the page is taken from the meigens closest to it,
+    // so that the previous page can be fetched with the first id of the current page.
+    // only `SortOrder::Newest` supports them.
+    pub older_than: Option<u32>,
+    pub newer_than: Option<u32>,
+
+    /// skipped from the cursor if it's given, or from the beginning.
     pub offset: u32,
     pub limit: u8,
 }
 
+impl FindOptions<'_> {
+    pub(crate) fn has_cursor(&self) -> bool {
+        self.older_than.is_some() || self.newer_than.is_some()
+    }
+}
+
+/// a page of the meigens matched to `FindOptions`.
+#[derive(Debug, Clone)]
+pub struct FindResult {
+    pub meigens: Vec<Meigen>,
+    /// how many meigens matched, regardless of cursors, offset and limit.
+    pub total: u32,
+    /// how many matched meigens come before this page.
+    pub preceding: u32,
+    pub limit: u8,
+}
+
+impl FindResult {
+    /// 1-based page number.
+    pub fn page(&self) -> u32 {
+        self.preceding / (self.limit.max(1) as u32) + 1
+    }
+
+    pub fn total_pages(&self) -> u32 {
+        let limit = self.limit.max(1) as u32;
+        self.total.div_ceil(limit).max(1)
+    }
+
+    pub fn has_next(&self) -> bool {
+        self.preceding + (self.meigens.len() as u32) < self.total
+    }
+
+    pub fn has_prev(&self) -> bool {
+        self.preceding > 0
+    }
+}
+
+/// returned by `MeigenDatabase::sample` when less meigens than requested are registered.
+/// use `anyhow::Error::is` to tell it from other errors.
+#[derive(Debug)]
+pub struct NotEnoughMeigens {
+    pub requested: u32,
+    pub available: u32,
+}
+
+impl std::fmt::Display for NotEnoughMeigens {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "requested {} meigens but only {} meigens are registered",
+            self.requested, self.available
+        )
+    }
+}
+
+impl std::error::Error for NotEnoughMeigens {}
+
 #[async_trait]
 pub trait MeigenDatabase: Send + Sync + 'static {
-    async fn save(&mut self, author: String, content: String) -> Result<Meigen>;
+    async fn save(
+        &mut self,
+        author: String,
+        content: String,
+        created_by: u64,
+        source: Option<MeigenSource>,
+    ) -> Result<Meigen>;
     async fn load(&self, id: u32) -> Result<Option<Meigen>>;
     async fn load_bulk(&self, id: &[u32]) -> Result<Vec<Meigen>>;
-    async fn delete(&mut self, id: u32) -> Result<bool>;
+    /// like `load_bulk`, but loads only the deleted ones, which can be restored.
+    async fn load_deleted_bulk(&self, id: &[u32]) -> Result<Vec<Meigen>>;
+    /// moves the meigen into the trash. deleted meigens can be brought back by `restore`.
+    async fn delete(&mut self, id: u32, deleted_by: u64) -> Result<bool>;
+    async fn restore(&mut self, id: u32, restored_by: u64) -> Result<bool>;
+
+    /// replaces author and content, and records the previous ones into the history.
+    /// returns None if the meigen doesn't exist.
+    async fn update(
+        &mut self,
+        id: u32,
+        author: String,
+        content: String,
+        edited_by: u64,
+    ) -> Result<Option<Meigen>>;
+
+    /// changes made to the meigen, oldest first.
+    async fn history(&self, id: u32) -> Result<Vec<HistoryEntry>>;
+
+    /// edits and deletes made to any meigen, newest first.
+    async fn recent_destructive_changes(&self, limit: u8) -> Result<Vec<HistoryEntry>>;
 
     async fn get_current_id(&self) -> Result<u32>;
 
-    async fn find(&self, options: FindOptions<'_>) -> Result<Vec<Meigen>>;
+    async fn find(&self, options: FindOptions<'_>) -> Result<FindResult>;
 
     async fn count(&self) -> Result<u32>;
 
+    /// authors ordered by the total loves of their meigens.
+    async fn top_authors(&self, offset: u32, limit: u8) -> Result<Vec<AuthorStats>>;
+
+    /// the number of distinct authors, which `top_authors` pages through.
+    async fn count_authors(&self) -> Result<u32>;
+
+    /// distinct authors starting with `prefix`, in ascending order. compared after `normalize`.
+    async fn authors_by_prefix(&self, prefix: &str, limit: u8) -> Result<Vec<String>>;
+
+    /// picks `count` distinct meigens at random.
+    async fn sample(&self, count: u32) -> Result<Vec<Meigen>>;
+
     async fn append_loved_user(&mut self, id: u32, loved_user_id: u64) -> Result<bool>;
     async fn remove_loved_user(&mut self, id: u32, loved_user_id: u64) -> Result<bool>;
 }
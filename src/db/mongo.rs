@@ -2,16 +2,25 @@ use std::convert::{TryFrom, TryInto};
 
 use anyhow::{Context, Result};
 use async_trait::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
 use mongodb::{
-    bson::{doc, from_document, Document},
-    options::ClientOptions,
-    Client, Collection,
+    bson::{doc, from_document, Bson, DateTime as BsonDateTime, Document},
+    options::{
+        ClientOptions, FindOneAndUpdateOptions, FindOptions as MongoFindOptions, IndexOptions,
+        ReturnDocument, UpdateOptions,
+    },
+    Client, Collection, IndexModel,
 };
 use serde::{Deserialize, Serialize};
 use tokio_stream::StreamExt;
 
-use super::FindOptions;
-use crate::{db::MeigenDatabase, model::Meigen, util::IteratorEditExt};
+use super::{Bounds, FindOptions, FindResult, NotEnoughMeigens, SortOrder};
+use crate::{
+    db::MeigenDatabase,
+    model::{AuthorStats, HistoryEntry, HistoryKind, Meigen, MeigenSource},
+    normalize::normalize,
+    util::IteratorEditExt,
+};
 
 #[derive(Serialize, Deserialize, Clone)]
 struct MongoMeigen {
@@ -22,6 +31,108 @@ struct MongoMeigen {
     // Added in PR #17. The attribute is for the backward compatibility.
     #[serde(default)]
     loved_user_id: Vec<String>,
+
+    // Added with the submitter metadata. The attributes are for the backward compatibility.
+    #[serde(default)]
+    created_at: Option<BsonDateTime>,
+    #[serde(default)]
+    created_by: Option<String>,
+    #[serde(default)]
+    updated_at: Option<BsonDateTime>,
+    #[serde(default)]
+    source: Option<MongoMeigenSource>,
+
+    // author and content folded by crate::normalize, which find searches on.
+    // meigens registered before they were introduced get them by `backfill_normalized` at the startup.
+    #[serde(default)]
+    normalized_author: Option<String>,
+    #[serde(default)]
+    normalized_content: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct MongoMeigenSource {
+    guild_id: Option<String>,
+    channel_id: String,
+    message_id: String,
+}
+
+impl From<MeigenSource> for MongoMeigenSource {
+    fn from(s: MeigenSource) -> Self {
+        Self {
+            guild_id: s.guild_id.map(|x| x.to_string()),
+            channel_id: s.channel_id.to_string(),
+            message_id: s.message_id.to_string(),
+        }
+    }
+}
+
+impl TryFrom<MongoMeigenSource> for MeigenSource {
+    type Error = anyhow::Error;
+
+    fn try_from(s: MongoMeigenSource) -> Result<MeigenSource> {
+        let parse = |x: String| x.parse().context("DB contains invalid value");
+
+        Ok(MeigenSource {
+            guild_id: s.guild_id.map(parse).transpose()?,
+            channel_id: parse(s.channel_id)?,
+            message_id: parse(s.message_id)?,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct MongoHistoryEntry {
+    meigen_id: i64,
+    kind: HistoryKind,
+    actor: String,
+    at: BsonDateTime,
+}
+
+impl TryFrom<MongoHistoryEntry> for HistoryEntry {
+    type Error = anyhow::Error;
+
+    fn try_from(h: MongoHistoryEntry) -> Result<HistoryEntry> {
+        Ok(HistoryEntry {
+            meigen_id: h.meigen_id as _,
+            kind: h.kind,
+            actor: h.actor.parse().context("DB contains invalid value")?,
+            at: into_chrono(h.at)?,
+        })
+    }
+}
+
+fn into_chrono(t: BsonDateTime) -> Result<DateTime<Utc>> {
+    Utc.timestamp_millis_opt(t.timestamp_millis())
+        .single()
+        .context("DB contains invalid datetime")
+}
+
+// the number of loves. loved_user_id is missing on meigens registered before PR #17.
+fn loves_expression() -> Document {
+    doc! { "$size": { "$ifNull": ["$loved_user_id", []] } }
+}
+
+fn into_range(bounds: Bounds) -> Option<Document> {
+    let mut doc = Document::new();
+
+    if let Some(min) = bounds.min {
+        doc.insert("$gte", min);
+    }
+
+    if let Some(max) = bounds.max {
+        doc.insert("$lte", max);
+    }
+
+    if doc.is_empty() {
+        None
+    } else {
+        Some(doc)
+    }
+}
+
+fn into_bson(t: DateTime<Utc>) -> BsonDateTime {
+    BsonDateTime::from_millis(t.timestamp_millis())
 }
 
 impl TryFrom<MongoMeigen> for Meigen {
@@ -34,73 +145,215 @@ impl TryFrom<MongoMeigen> for Meigen {
             .map(|id| id.parse().context("DB contains invalid value"))
             .collect::<Result<Vec<u64>>>()?;
 
+        let created_by = m
+            .created_by
+            .map(|id| id.parse().context("DB contains invalid value"))
+            .transpose()?;
+
         Ok(Meigen {
             id: m.id as _,
             author: m.author,
             content: m.content,
             loved_user_id,
+            created_at: m.created_at.map(into_chrono).transpose()?,
+            created_by,
+            updated_at: m.updated_at.map(into_chrono).transpose()?,
+            source: m.source.map(TryInto::try_into).transpose()?,
         })
     }
 }
 
+// _id of the document in "counters" collection which holds the last allocated meigen id.
+const MEIGEN_ID_COUNTER: &str = "meigen_id";
+
+// set on deleted meigens. they stay in "entries", so that deleting and restoring are one atomic update.
+// null also matches the meigens without the field.
+const DELETED_AT: &str = "deleted_at";
+
+fn alive() -> Document {
+    doc! { DELETED_AT: Bson::Null }
+}
+
+fn deleted() -> Document {
+    doc! { DELETED_AT: { "$ne": Bson::Null } }
+}
+
 pub struct MongoMeigenDatabase {
     inner: Collection<MongoMeigen>,
+    counters: Collection<Document>,
+    history: Collection<MongoHistoryEntry>,
 }
 
 impl MongoMeigenDatabase {
     pub async fn new(url: &str) -> Result<Self> {
+        Self::open(url, "meigen").await
+    }
+
+    async fn open(url: &str, database: &str) -> Result<Self> {
         let opt = ClientOptions::parse(url)
             .await
             .context("failed to parse mongodb url")?;
 
-        let collection = Client::with_options(opt)
+        let database = Client::with_options(opt)
             .context("failed to create mongodb client")?
-            .database("meigen")
-            .collection("entries");
+            .database(database);
 
-        Ok(Self { inner: collection })
-    }
-}
+        let db = Self {
+            inner: database.collection("entries"),
+            counters: database.collection("counters"),
+            history: database.collection("history"),
+        };
 
-#[async_trait]
-impl MeigenDatabase for MongoMeigenDatabase {
-    async fn save(&mut self, author: String, content: String) -> anyhow::Result<Meigen> {
-        // FIXME: use transaction
-        let current_id = self
-            .get_current_id()
+        db.history
+            .create_index(
+                IndexModel::builder().keys(doc! { "meigen_id": 1 }).build(),
+                None,
+            )
             .await
-            .context("failed to get current head meigen id")? as i64;
+            .context("failed to create index for history")?;
 
-        let id = current_id + 1;
+        // ids could be duplicated by concurrent saves before the counter was introduced.
+        // the unique index can't be built on them, and which copy should keep the id is up to admins.
+        let duplicated = db
+            .duplicated_ids()
+            .await
+            .context("failed to look for duplicated ids")?;
 
-        let meigen = MongoMeigen {
-            id,
-            author,
-            content,
-            loved_user_id: Vec::new(),
+        if !duplicated.is_empty() {
+            anyhow::bail!(
+                "some meigens share the same id: {}. give the extra copies unused ids \
+                 (larger than any existing one) or delete them, and then restart. \
+                 e.g. `db.entries.updateOne({{ _id: ObjectId(\"...\") }}, {{ $set: {{ id: 1234 }} }})`",
+                duplicated
+                    .iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+
+        db.inner
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "id": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+                None,
+            )
+            .await
+            .context("failed to create unique index for id")?;
+
+        // initialize the counter for databases created before the counter was introduced.
+        // "$max" never moves the counter backward, so this is safe even if the counter exists.
+        let current_id = db
+            .max_id()
+            .await
+            .context("failed to get current head meigen id")?;
+
+        db.counters
+            .update_one(
+                doc! { "_id": MEIGEN_ID_COUNTER },
+                doc! { "$max": { "seq": current_id as i64 } },
+                UpdateOptions::builder().upsert(true).build(),
+            )
+            .await
+            .context("failed to initialize meigen id counter")?;
+
+        db.backfill_normalized()
+            .await
+            .context("failed to fill normalized fields")?;
+
+        Ok(db)
+    }
+
+    // fills normalized_author and normalized_content of the meigens saved before they were introduced.
+    // normalize can't be done in mongodb, so they are updated one by one. does nothing once all are filled.
+    async fn backfill_normalized(&self) -> Result<()> {
+        let missing = doc! {
+            "$or": [
+                { "normalized_author": { "$exists": false } },
+                { "normalized_content": { "$exists": false } },
+            ]
         };
 
-        self.inner
-            .insert_one(meigen.clone(), None)
+        let mut cursor = self
+            .inner
+            .find(missing, None)
             .await
-            .context("failed to insert meigen")?;
+            .context("failed to find meigens to fill")?;
 
-        meigen.try_into()
+        let mut filled = 0;
+
+        while let Some(meigen) = cursor.next().await {
+            let meigen = meigen.context("failed to decode meigen")?;
+
+            // skip it if it was edited meanwhile. update sets the fields by itself.
+            self.inner
+                .update_one(
+                    doc! {
+                        "id": meigen.id,
+                        "author": meigen.author.as_str(),
+                        "content": meigen.content.as_str(),
+                    },
+                    doc! {
+                        "$set": {
+                            "normalized_author": normalize(&meigen.author),
+                            "normalized_content": normalize(&meigen.content),
+                        }
+                    },
+                    None,
+                )
+                .await
+                .context("failed to fill normalized fields")?;
+
+            filled += 1;
+        }
+
+        if filled > 0 {
+            tracing::info!("filled normalized fields of {} meigens", filled);
+        }
+
+        Ok(())
     }
 
-    async fn load(&self, id: u32) -> anyhow::Result<Option<Meigen>> {
-        self.inner
-            .find_one(doc! { "id": id }, None)
+    async fn record(&self, meigen_id: u32, kind: HistoryKind, actor: u64) -> Result<()> {
+        self.history
+            .insert_one(
+                MongoHistoryEntry {
+                    meigen_id: meigen_id as _,
+                    kind,
+                    actor: actor.to_string(),
+                    at: into_bson(Utc::now()),
+                },
+                None,
+            )
             .await
-            .context("failed to find meigen")?
-            .map(TryFrom::try_from)
-            .transpose()
-            .context("failed to deserialize the meigen")
+            .context("failed to insert history")
+            .map(|_| ())
     }
 
-    async fn load_bulk(&self, id: &[u32]) -> anyhow::Result<Vec<Meigen>> {
+    async fn allocate_id(&self) -> Result<i64> {
+        self.counters
+            .find_one_and_update(
+                doc! { "_id": MEIGEN_ID_COUNTER },
+                doc! { "$inc": { "seq": 1_i64 } },
+                FindOneAndUpdateOptions::builder()
+                    .upsert(true)
+                    .return_document(ReturnDocument::After)
+                    .build(),
+            )
+            .await
+            .context("failed to increment meigen id counter")?
+            .context("meigen id counter returned nothing")?
+            .get_i64("seq")
+            .context("meigen id counter's seq property isn't i64")
+    }
+
+    async fn find_by_ids(&self, id: &[u32], mut filter: Document) -> Result<Vec<Meigen>> {
+        filter.insert("id", doc! { "$in": id });
+
         self.inner
-            .find(doc! { "id": { "$in": id } }, None)
+            .find(filter, None)
             .await
             .context("failed to make find request")?
             .map(|x| {
@@ -112,16 +365,52 @@ impl MeigenDatabase for MongoMeigenDatabase {
             .context("failed to decode meigen")
     }
 
-    async fn delete(&mut self, id: u32) -> anyhow::Result<bool> {
+    async fn aggregate_meigens(&self, pipeline: Vec<Document>) -> Result<Vec<Meigen>> {
         self.inner
-            .delete_one(doc! { "id": id }, None)
+            .aggregate(pipeline, None)
             .await
-            .context("failed to delete meigen")
-            .map(|x| x.deleted_count == 1)
+            .context("failed to aggregate")?
+            .map(|x| x.context("failed to decode document"))
+            .map(|x| {
+                x.and_then(|x| {
+                    from_document::<MongoMeigen>(x).context("failed to deserialize document")
+                })
+            })
+            .map(|x| {
+                x.map(TryFrom::try_from)
+                    .context("failed to deserialize the meigen")?
+            })
+            .collect::<Result<Vec<Meigen>, _>>()
+            .await
+            .context("failed to fetch aggregated documents")
     }
 
-    async fn get_current_id(&self) -> anyhow::Result<u32> {
+    async fn duplicated_ids(&self) -> Result<Vec<i64>> {
         self.inner
+            .aggregate(
+                vec![
+                    doc! { "$group": { "_id": "$id", "count": { "$sum": 1 } } },
+                    doc! { "$match": { "count": { "$gt": 1 } } },
+                    doc! { "$sort": { "_id": 1 } },
+                ],
+                None,
+            )
+            .await
+            .context("failed to aggregate")?
+            .map(|x| {
+                x.context("failed to fetch aggregated result")?
+                    .get_i64("_id")
+                    .context("aggregated id isn't i64")
+            })
+            .collect::<Result<Vec<_>>>()
+            .await
+    }
+
+    // the largest id currently stored.
+    // this can be smaller than the counter if the newest meigen was deleted.
+    async fn max_id(&self) -> Result<u32> {
+        let aggregated = self
+            .inner
             .aggregate(
                 vec![doc! {
                     "$group": {
@@ -136,95 +425,913 @@ impl MeigenDatabase for MongoMeigenDatabase {
             .await
             .context("failed to aggregate")?
             .next()
-            .await
-            .context("aggregation returned nothing")?
-            .context("failed to fetch aggregated result")?
+            .await;
+
+        // aggregation returns nothing when the collection is empty.
+        let aggregated = match aggregated {
+            Some(doc) => doc.context("failed to fetch aggregated result")?,
+            None => return Ok(0),
+        };
+
+        aggregated
             .get("current_id")
             .context("returned document doesn't have current_id property")?
             .as_i64()
             .context("returned document's current_id property isn't i64")
             .map(|x| x as u32)
     }
+}
+
+#[async_trait]
+impl MeigenDatabase for MongoMeigenDatabase {
+    async fn save(
+        &mut self,
+        author: String,
+        content: String,
+        created_by: u64,
+        source: Option<MeigenSource>,
+    ) -> anyhow::Result<Meigen> {
+        // the counter is incremented atomically, so concurrent writers never get the same id.
+        // the unique index on id rejects the insertion if it ever happened.
+        let id = self
+            .allocate_id()
+            .await
+            .context("failed to allocate meigen id")?;
+
+        let now = into_bson(Utc::now());
+
+        let meigen = MongoMeigen {
+            id,
+            normalized_author: Some(normalize(&author)),
+            normalized_content: Some(normalize(&content)),
+            author,
+            content,
+            loved_user_id: Vec::new(),
+            created_at: Some(now),
+            created_by: Some(created_by.to_string()),
+            updated_at: Some(now),
+            source: source.map(From::from),
+        };
 
-    async fn find(&self, options: FindOptions<'_>) -> anyhow::Result<Vec<Meigen>> {
         self.inner
-            .aggregate(
-                vec![
-                    {
-                        let into_regex = |x| doc! { "$regex": format!(".*{}.*", regex::escape(x)) };
-                        let mut doc = Document::new();
+            .insert_one(meigen.clone(), None)
+            .await
+            .context("failed to insert meigen")?;
 
-                        if let Some(author) = options.author {
-                            doc.insert("author", into_regex(author));
-                        }
+        self.record(id as _, HistoryKind::Create, created_by)
+            .await?;
 
-                        if let Some(content) = options.content {
-                            doc.insert("content", into_regex(content));
-                        }
+        meigen.try_into()
+    }
+
+    async fn load(&self, id: u32) -> anyhow::Result<Option<Meigen>> {
+        self.inner
+            .find_one(doc! { "id": id, DELETED_AT: Bson::Null }, None)
+            .await
+            .context("failed to find meigen")?
+            .map(TryFrom::try_from)
+            .transpose()
+            .context("failed to deserialize the meigen")
+    }
+
+    async fn load_bulk(&self, id: &[u32]) -> anyhow::Result<Vec<Meigen>> {
+        self.find_by_ids(id, alive()).await
+    }
+
+    async fn load_deleted_bulk(&self, id: &[u32]) -> anyhow::Result<Vec<Meigen>> {
+        self.find_by_ids(id, deleted()).await
+    }
+
+    async fn delete(&mut self, id: u32, deleted_by: u64) -> anyhow::Result<bool> {
+        let deleted = self
+            .inner
+            .update_one(
+                doc! { "id": id, DELETED_AT: Bson::Null },
+                doc! { "$set": { DELETED_AT: into_bson(Utc::now()) } },
+                None,
+            )
+            .await
+            .context("failed to delete meigen")
+            .map(|x| x.modified_count == 1)?;
 
-                        doc! { "$match": doc }
+        if deleted {
+            self.record(id, HistoryKind::Delete, deleted_by).await?;
+        }
+
+        Ok(deleted)
+    }
+
+    async fn restore(&mut self, id: u32, restored_by: u64) -> anyhow::Result<bool> {
+        let mut filter = deleted();
+        filter.insert("id", id);
+
+        let restored = self
+            .inner
+            .update_one(filter, doc! { "$unset": { DELETED_AT: "" } }, None)
+            .await
+            .context("failed to restore meigen")
+            .map(|x| x.modified_count == 1)?;
+
+        if restored {
+            self.record(id, HistoryKind::Restore, restored_by).await?;
+        }
+
+        Ok(restored)
+    }
+
+    async fn update(
+        &mut self,
+        id: u32,
+        author: String,
+        content: String,
+        edited_by: u64,
+    ) -> anyhow::Result<Option<Meigen>> {
+        let now = into_bson(Utc::now());
+
+        let previous = self
+            .inner
+            .find_one_and_update(
+                doc! { "id": id, DELETED_AT: Bson::Null },
+                doc! {
+                    "$set": {
+                        "author": author.as_str(),
+                        "content": content.as_str(),
+                        "normalized_author": normalize(&author),
+                        "normalized_content": normalize(&content),
+                        "updated_at": now,
+                    }
+                },
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::Before)
+                    .build(),
+            )
+            .await
+            .context("failed to update meigen")?;
+
+        let previous = match previous {
+            Some(p) => p,
+            None => return Ok(None),
+        };
+
+        self.history
+            .insert_one(
+                MongoHistoryEntry {
+                    meigen_id: id as _,
+                    kind: HistoryKind::Edit {
+                        previous_author: previous.author.clone(),
+                        previous_content: previous.content.clone(),
                     },
-                    doc! { "$sort": { "id": -1 } },
+                    actor: edited_by.to_string(),
+                    at: now,
+                },
+                None,
+            )
+            .await
+            .context("failed to insert history")?;
+
+        let mut meigen = Meigen::try_from(previous).context("failed to deserialize the meigen")?;
+        meigen.author = author;
+        meigen.content = content;
+        meigen.updated_at = Some(into_chrono(now)?);
+
+        Ok(Some(meigen))
+    }
+
+    async fn history(&self, id: u32) -> anyhow::Result<Vec<HistoryEntry>> {
+        self.history
+            .find(doc! { "meigen_id": id }, None)
+            .await
+            .context("failed to make find request")?
+            .map(|x| {
+                x.map(TryFrom::try_from)
+                    .context("failed to deserialize the history")?
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .await
+            .edit(|x| x.sort_by_key(|x: &HistoryEntry| x.at))
+            .context("failed to decode history")
+    }
+
+    async fn recent_destructive_changes(&self, limit: u8) -> anyhow::Result<Vec<HistoryEntry>> {
+        // must be kept in sync with HistoryKind::is_destructive.
+        let destructive_kinds = ["edit", "delete"];
+
+        self.history
+            .find(
+                doc! { "kind.type": { "$in": destructive_kinds.to_vec() } },
+                MongoFindOptions::builder()
+                    .sort(doc! { "at": -1 })
+                    .limit(limit as i64)
+                    .build(),
+            )
+            .await
+            .context("failed to make find request")?
+            .map(|x| {
+                x.map(TryFrom::try_from)
+                    .context("failed to deserialize the history")?
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .await
+            .context("failed to decode history")
+    }
+
+    async fn get_current_id(&self) -> anyhow::Result<u32> {
+        // the counter is never decremented, so deleted ids are never handed out again.
+        let counter = self
+            .counters
+            .find_one(doc! { "_id": MEIGEN_ID_COUNTER }, None)
+            .await
+            .context("failed to find meigen id counter")?;
+
+        match counter {
+            Some(counter) => counter
+                .get_i64("seq")
+                .context("meigen id counter's seq property isn't i64")
+                .map(|x| x as u32),
+
+            None => Ok(0),
+        }
+    }
+
+    async fn find(&self, options: FindOptions<'_>) -> anyhow::Result<FindResult> {
+        anyhow::ensure!(
+            options.sort == SortOrder::Newest || !options.has_cursor(),
+            "cursors are supported only with SortOrder::Newest"
+        );
+
+        let filter = {
+            // compare normalized ones, so that the result is same as MemoryMeigenDatabase.
+            let into_regex = |x| doc! { "$regex": format!(".*{}.*", regex::escape(&normalize(x))) };
+            let mut doc = alive();
+
+            if let Some(author) = options.author {
+                doc.insert("normalized_author", into_regex(author));
+            }
+
+            if let Some(content) = options.content {
+                doc.insert("normalized_content", into_regex(content));
+            }
+
+            if !options.keywords.is_empty() {
+                let keywords = options
+                    .keywords
+                    .iter()
+                    .map(|x| {
+                        doc! {
+                            "$or": [
+                                { "normalized_author": into_regex(x) },
+                                { "normalized_content": into_regex(x) },
+                            ]
+                        }
+                    })
+                    .collect::<Vec<_>>();
+
+                doc.insert("$and", keywords);
+            }
+
+            if !options.excludes.is_empty() {
+                let excludes = options
+                    .excludes
+                    .iter()
+                    .flat_map(|x| {
+                        vec![
+                            doc! { "normalized_author": into_regex(x) },
+                            doc! { "normalized_content": into_regex(x) },
+                        ]
+                    })
+                    .collect::<Vec<_>>();
+
+                doc.insert("$nor", excludes);
+            }
+
+            // loves is not stored, so it's compared in an aggregation expression.
+            if !options.loves.is_unbounded() {
+                let mut loves = vec![];
+
+                if let Some(min) = options.loves.min {
+                    loves.push(doc! { "$gte": [loves_expression(), min] });
+                }
+
+                if let Some(max) = options.loves.max {
+                    loves.push(doc! { "$lte": [loves_expression(), max] });
+                }
+
+                doc.insert("$expr", doc! { "$and": loves });
+            }
+
+            if let Some(id) = into_range(options.id) {
+                doc.insert("id", id);
+            }
+
+            doc
+        };
+
+        // the filter may already have a range on id, so the cursors are combined with $and.
+        let with_id = |range: Document| doc! { "$and": [filter.clone(), { "id": range }] };
+
+        let total = self
+            .inner
+            .count_documents(filter.clone(), None)
+            .await
+            .context("failed to count matching meigens")? as u32;
+
+        let meigens = match options.sort {
+            // sorted by a computed value, which only an aggregation can do. this never has cursors.
+            SortOrder::MostLoved => {
+                self.aggregate_meigens(vec![
+                    doc! { "$match": filter.clone() },
+                    doc! { "$addFields": { "loves": loves_expression() } },
+                    doc! { "$sort": { "loves": -1, "id": -1 } },
                     doc! { "$skip": options.offset },
                     doc! { "$limit": options.limit as u32 },
+                ])
+                .await?
+            }
+
+            SortOrder::Newest => {
+                let (page_filter, sort) = match (options.newer_than, options.older_than) {
+                    (None, None) => (filter.clone(), -1),
+
+                    (newer_than, Some(older_than)) => {
+                        let mut range = doc! { "$lt": older_than };
+
+                        if let Some(newer_than) = newer_than {
+                            range.insert("$gt", newer_than);
+                        }
+
+                        (with_id(range), -1)
+                    }
+
+                    // take the closest ones to newer_than by sorting them in the reverse order.
+                    (Some(newer_than), None) => (with_id(doc! { "$gt": newer_than }), 1),
+                };
+
+                let find_options = MongoFindOptions::builder()
+                    .sort(doc! { "id": sort })
+                    .skip(options.offset as u64)
+                    .limit(options.limit as i64)
+                    .build();
+
+                let mut meigens = self
+                    .inner
+                    .find(page_filter, find_options)
+                    .await
+                    .context("failed to make find request")?
+                    .map(|x| {
+                        x.map(TryFrom::try_from)
+                            .context("failed to deserialize the meigen")?
+                    })
+                    .collect::<Result<Vec<Meigen>, _>>()
+                    .await
+                    .context("failed to decode meigen")?;
+
+                meigens.sort_unstable_by_key(|x| x.id);
+                meigens
+            }
+        };
+
+        let count_with_id = |range: Document| async move {
+            self.inner
+                .count_documents(with_id(range), None)
+                .await
+                .context("failed to count preceding meigens")
+                .map(|x| x as u32)
+        };
+
+        let preceding = match (options.newer_than, options.older_than) {
+            (None, None) => options.offset,
+
+            (_, Some(older_than)) => {
+                count_with_id(doc! { "$gte": older_than }).await? + options.offset
+            }
+
+            // the ones newer than newer_than include this page.
+            (Some(newer_than), None) => count_with_id(doc! { "$gt": newer_than })
+                .await?
+                .saturating_sub(options.offset)
+                .saturating_sub(meigens.len() as u32),
+        };
+
+        Ok(FindResult {
+            meigens,
+            total,
+            preceding,
+            limit: options.limit,
+        })
+    }
+
+    async fn count(&self) -> anyhow::Result<u32> {
+        self.inner
+            .count_documents(alive(), None)
+            .await
+            .context("failed to count documents")
+            .map(|x| x as u32)
+    }
+
+    async fn top_authors(&self, offset: u32, limit: u8) -> anyhow::Result<Vec<AuthorStats>> {
+        #[derive(Deserialize)]
+        struct MongoAuthorStats {
+            #[serde(rename = "_id")]
+            author: String,
+            loves: i64,
+            meigens: i64,
+        }
+
+        self.inner
+            .aggregate(
+                vec![
+                    doc! { "$match": alive() },
+                    doc! {
+                        "$group": {
+                            "_id": "$author",
+                            "loves": { "$sum": loves_expression() },
+                            "meigens": { "$sum": 1 },
+                        }
+                    },
+                    doc! { "$sort": { "loves": -1, "_id": 1 } },
+                    doc! { "$skip": offset },
+                    doc! { "$limit": limit as u32 },
                 ],
                 None,
             )
             .await
             .context("failed to aggregate")?
-            .map(|x| x.context("failed to decode document"))
             .map(|x| {
-                x.and_then(|x| {
-                    from_document::<MongoMeigen>(x).context("failed to deserialize document")
+                let stats =
+                    from_document::<MongoAuthorStats>(x.context("failed to decode document")?)
+                        .context("failed to deserialize document")?;
+
+                Ok(AuthorStats {
+                    author: stats.author,
+                    loves: stats.loves as u32,
+                    meigens: stats.meigens as u32,
                 })
             })
-            .map(|x| {
-                x.map(TryFrom::try_from)
-                    .context("failed to deserialize the meigen")?
-            })
-            .collect::<Result<Vec<Meigen>, _>>()
+            .collect::<Result<Vec<_>>>()
             .await
-            .edit(|x| x.sort_unstable_by_key(|x| x.id))
             .context("failed to fetch aggregated documents")
     }
 
-    async fn count(&self) -> anyhow::Result<u32> {
-        self.inner
-            .aggregate(vec![doc! { "$count": "id" }], None)
+    async fn count_authors(&self) -> anyhow::Result<u32> {
+        let counted = self
+            .inner
+            .aggregate(
+                vec![
+                    doc! { "$match": alive() },
+                    doc! { "$group": { "_id": "$author" } },
+                    doc! { "$count": "authors" },
+                ],
+                None,
+            )
             .await
             .context("failed to aggregate")?
             .next()
-            .await
-            .context("aggregation returned nothing")?
-            .context("failed to fetch aggregated result")?
-            .get("id")
-            .context("returned document doesn't have id property")?
-            .as_i32()
-            .context("returned document's id property wasn't i64")
+            .await;
+
+        // $count returns nothing instead of 0 when no document matched.
+        let counted = match counted {
+            Some(doc) => doc.context("failed to fetch aggregated result")?,
+            None => return Ok(0),
+        };
+
+        counted
+            .get_i32("authors")
+            .context("returned document's authors property isn't i32")
             .map(|x| x as u32)
     }
 
-    async fn append_loved_user(&mut self, id: u32, loved_user_id: u64) -> Result<bool> {
+    async fn authors_by_prefix(&self, prefix: &str, limit: u8) -> anyhow::Result<Vec<String>> {
+        #[derive(Deserialize)]
+        struct MongoAuthor {
+            #[serde(rename = "_id")]
+            author: String,
+        }
+
+        let regex = format!("^{}", regex::escape(&normalize(prefix)));
+
         self.inner
+            .aggregate(
+                vec![
+                    doc! { "$match": { "normalized_author": { "$regex": regex }, DELETED_AT: Bson::Null } },
+                    doc! { "$group": { "_id": "$author" } },
+                    doc! { "$sort": { "_id": 1 } },
+                    doc! { "$limit": limit as u32 },
+                ],
+                None,
+            )
+            .await
+            .context("failed to aggregate")?
+            .map(|x| {
+                let author = from_document::<MongoAuthor>(x.context("failed to decode document")?)
+                    .context("failed to deserialize document")?;
+
+                Ok(author.author)
+            })
+            .collect::<Result<Vec<_>>>()
+            .await
+            .context("failed to fetch aggregated documents")
+    }
+
+    async fn sample(&self, count: u32) -> anyhow::Result<Vec<Meigen>> {
+        let available = self.count().await.context("failed to get meigen count")?;
+
+        if available < count {
+            return Err(NotEnoughMeigens {
+                requested: count,
+                available,
+            }
+            .into());
+        }
+
+        let meigens = self
+            .aggregate_meigens(vec![
+                doc! { "$match": alive() },
+                doc! { "$sample": { "size": count } },
+            ])
+            .await?;
+
+        // meigens can be deleted between count and $sample.
+        if meigens.len() < count as usize {
+            return Err(NotEnoughMeigens {
+                requested: count,
+                available: meigens.len() as _,
+            }
+            .into());
+        }
+
+        Ok(meigens)
+    }
+
+    async fn append_loved_user(&mut self, id: u32, loved_user_id: u64) -> Result<bool> {
+        let appended = self
+            .inner
             .update_one(
-                doc! { "id": id },
+                doc! { "id": id, DELETED_AT: Bson::Null },
                 doc! { "$addToSet": { "loved_user_id": loved_user_id.to_string() } },
                 None,
             )
             .await
             .context("failed to append loved user id")
-            .map(|x| x.modified_count == 1)
+            .map(|x| x.modified_count == 1)?;
+
+        if appended {
+            self.record(id, HistoryKind::Love, loved_user_id).await?;
+        }
+
+        Ok(appended)
     }
 
     async fn remove_loved_user(&mut self, id: u32, loved_user_id: u64) -> Result<bool> {
-        self.inner
+        let removed = self
+            .inner
             .update_one(
-                doc! { "id": id },
+                doc! { "id": id, DELETED_AT: Bson::Null },
                 doc! { "$pull": { "loved_user_id": loved_user_id.to_string() } },
                 None,
             )
             .await
             .context("failed to remove loved user id")
-            .map(|x| x.modified_count == 1)
+            .map(|x| x.modified_count == 1)?;
+
+        if removed {
+            self.record(id, HistoryKind::Unlove, loved_user_id).await?;
+        }
+
+        Ok(removed)
+    }
+}
+
+// these need a running mongod. run them with
+// `MONGODB_TEST_URI=mongodb://localhost:27017 cargo test --lib --features memorydb,mongodb_ -- --ignored`
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::collections::HashSet;
+
+    use mongodb::Client;
+
+    use super::*;
+
+    /// a database with a random name, which is dropped by `drop_test_database`.
+    pub(crate) fn test_database() -> (String, String) {
+        let url = std::env::var("MONGODB_TEST_URI").expect("MONGODB_TEST_URI is not set");
+        let name = format!("meigen_test_{:016x}", rand::random::<u64>());
+        (url, name)
+    }
+
+    pub(crate) async fn open_test_database(url: &str, name: &str) -> MongoMeigenDatabase {
+        MongoMeigenDatabase::open(url, name)
+            .await
+            .expect("failed to open test database")
+    }
+
+    pub(crate) async fn drop_test_database(url: &str, name: &str) {
+        Client::with_uri_str(url)
+            .await
+            .unwrap()
+            .database(name)
+            .drop(None)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore = "needs a running mongod, see the comment above"]
+    async fn concurrent_saves_get_unique_and_gap_free_ids() {
+        const WRITERS: u32 = 8;
+        const SAVES_PER_WRITER: u32 = 25;
+
+        let (url, name) = test_database();
+
+        // one client per writer, like separate bot processes sharing the database.
+        let mut writers = vec![];
+
+        for w in 0..WRITERS {
+            let mut db = open_test_database(&url, &name).await;
+
+            writers.push(tokio::spawn(async move {
+                let mut ids = vec![];
+
+                for i in 0..SAVES_PER_WRITER {
+                    let meigen = db
+                        .save(format!("writer {}", w), format!("meigen {}", i), 1, None)
+                        .await
+                        .unwrap();
+
+                    ids.push(meigen.id);
+                }
+
+                ids
+            }));
+        }
+
+        let mut ids = vec![];
+
+        for writer in writers {
+            ids.extend(writer.await.unwrap());
+        }
+
+        let db = open_test_database(&url, &name).await;
+        let total = WRITERS * SAVES_PER_WRITER;
+
+        let unique = ids.iter().copied().collect::<HashSet<_>>();
+        assert_eq!(unique.len(), ids.len(), "an id was allocated twice");
+
+        ids.sort_unstable();
+        assert_eq!(ids, (1..=total).collect::<Vec<_>>());
+
+        assert_eq!(db.count().await.unwrap(), total);
+        assert_eq!(db.get_current_id().await.unwrap(), total);
+
+        drop_test_database(&url, &name).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a running mongod, see the comment above"]
+    async fn refuses_to_open_with_duplicated_ids() {
+        let (url, name) = test_database();
+
+        let entry = |id: i64| doc! { "id": id, "author": "a", "content": "b", "loved_user_id": [] };
+
+        Client::with_uri_str(&url)
+            .await
+            .unwrap()
+            .database(&name)
+            .collection::<Document>("entries")
+            .insert_many(vec![entry(1), entry(2), entry(2), entry(3), entry(3)], None)
+            .await
+            .unwrap();
+
+        let error = MongoMeigenDatabase::open(&url, &name)
+            .await
+            .err()
+            .expect("opened the database with duplicated ids");
+
+        assert!(format!("{:#}", error).contains("share the same id: 2, 3."));
+
+        drop_test_database(&url, &name).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a running mongod, see the comment above"]
+    async fn deleted_meigens_are_hidden_until_restored() {
+        let (url, name) = test_database();
+        let mut db = open_test_database(&url, &name).await;
+
+        let kept = db.save("a".into(), "kept".into(), 1, None).await.unwrap();
+        let deleted = db
+            .save("b".into(), "deleted".into(), 1, None)
+            .await
+            .unwrap();
+
+        assert!(db.delete(deleted.id, 2).await.unwrap());
+        assert!(!db.delete(deleted.id, 2).await.unwrap());
+
+        assert!(db.load(deleted.id).await.unwrap().is_none());
+        assert_eq!(db.count().await.unwrap(), 1);
+        assert_eq!(db.count_authors().await.unwrap(), 1);
+        assert_eq!(
+            db.authors_by_prefix("b", 10).await.unwrap(),
+            Vec::<String>::new()
+        );
+        assert!(!db.append_loved_user(deleted.id, 3).await.unwrap());
+
+        let found = db
+            .find(FindOptions {
+                limit: 10,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            found.meigens.iter().map(|x| x.id).collect::<Vec<_>>(),
+            vec![kept.id]
+        );
+
+        let trashed = db.load_deleted_bulk(&[kept.id, deleted.id]).await.unwrap();
+        assert_eq!(
+            trashed.iter().map(|x| x.id).collect::<Vec<_>>(),
+            vec![deleted.id]
+        );
+
+        assert!(db.restore(deleted.id, 2).await.unwrap());
+        assert!(!db.restore(deleted.id, 2).await.unwrap());
+
+        assert_eq!(
+            db.load(deleted.id).await.unwrap().unwrap().content,
+            "deleted"
+        );
+        assert!(db
+            .load_deleted_bulk(&[deleted.id])
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(db.count_authors().await.unwrap(), 2);
+
+        // the id of the deleted one is never handed out again.
+        let next = db.save("c".into(), "next".into(), 1, None).await.unwrap();
+        assert_eq!(next.id, deleted.id + 1);
+
+        drop_test_database(&url, &name).await;
+    }
+
+    #[cfg(any(feature = "memorydb", feature = "filedb"))]
+    #[tokio::test]
+    #[ignore = "needs a running mongod, see the comment above"]
+    async fn cursors_match_memory_backend() {
+        use crate::db::mem::MemoryMeigenDatabase;
+
+        let (url, name) = test_database();
+        let mut mongo = open_test_database(&url, &name).await;
+        let mut memory = MemoryMeigenDatabase::new();
+
+        for db in [&mut mongo as &mut dyn MeigenDatabase, &mut memory] {
+            for i in 1..=7 {
+                db.save("a".into(), format!("meigen {}", i), 1, None)
+                    .await
+                    .unwrap();
+            }
+
+            assert!(db.delete(4, 1).await.unwrap());
+        }
+
+        let cursors = [
+            (None, None),
+            (Some(6), None),
+            (Some(3), None),
+            (Some(1), None),
+            (None, Some(2)),
+            (None, Some(5)),
+            (None, Some(7)),
+            (Some(4), None),
+            (None, Some(4)),
+            (Some(7), Some(3)),
+        ];
+
+        for (older_than, newer_than) in cursors {
+            let options = || FindOptions {
+                older_than,
+                newer_than,
+                limit: 2,
+                ..Default::default()
+            };
+
+            // the order in the page differs between the backends.
+            let summary = |x: FindResult| {
+                let mut ids = x.meigens.iter().map(|x| x.id).collect::<Vec<_>>();
+                ids.sort_unstable();
+                (ids, x.preceding, x.total)
+            };
+
+            assert_eq!(
+                summary(mongo.find(options()).await.unwrap()),
+                summary(memory.find(options()).await.unwrap()),
+                "older_than: {:?}, newer_than: {:?}",
+                older_than,
+                newer_than
+            );
+        }
+
+        drop_test_database(&url, &name).await;
+    }
+
+    // the meigens in the same order on both backends, so that they get the same ids.
+    #[cfg(any(feature = "memorydb", feature = "filedb"))]
+    const MEIGENS: &[(&str, &str)] = &[
+        ("かわえもん", "ＡＢＣ ｶﾀｶﾅ"),
+        ("カワエモン", "abc かたかな"),
+        ("KawaEmon", "Hello World"),
+        ("kawaemon", "ｈｅｌｌｏ"),
+        ("ｶﾜｴﾓﾝ", "草"),
+        ("someone", "HELLO カタカナ"),
+    ];
+
+    // the first ones are written without the normalized fields, like the meigens saved before they existed.
+    #[cfg(any(feature = "memorydb", feature = "filedb"))]
+    const LEGACY_MEIGENS: usize = 3;
+
+    #[cfg(any(feature = "memorydb", feature = "filedb"))]
+    #[tokio::test]
+    #[ignore = "needs a running mongod, see the comment above"]
+    async fn normalized_queries_match_memory_backend() {
+        use crate::{db::mem::MemoryMeigenDatabase, query::Query};
+
+        let (url, name) = test_database();
+
+        let legacy = MEIGENS[..LEGACY_MEIGENS]
+            .iter()
+            .zip(1_i64..)
+            .map(|((author, content), id)| {
+                doc! { "id": id, "author": *author, "content": *content }
+            })
+            .collect::<Vec<_>>();
+
+        Client::with_uri_str(&url)
+            .await
+            .unwrap()
+            .database(&name)
+            .collection::<Document>("entries")
+            .insert_many(legacy, None)
+            .await
+            .unwrap();
+
+        // fills the legacy ones.
+        let mut mongo = open_test_database(&url, &name).await;
+        let mut memory = MemoryMeigenDatabase::new();
+
+        for (author, content) in &MEIGENS[..LEGACY_MEIGENS] {
+            memory
+                .save(author.to_string(), content.to_string(), 1, None)
+                .await
+                .unwrap();
+        }
+
+        for (author, content) in &MEIGENS[LEGACY_MEIGENS..] {
+            for db in [&mut mongo as &mut dyn MeigenDatabase, &mut memory] {
+                db.save(author.to_string(), content.to_string(), 1, None)
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let queries = [
+            "author:かわえもん",
+            "author:KAWA",
+            "author:ｶﾜ",
+            "content:abc",
+            "content:ＨＥＬＬＯ",
+            "カタカナ",
+            "かわ hello",
+            "-かたかな",
+            "hello -someone",
+            "author:kawa content:abc",
+            "nothing",
+        ];
+
+        for query in queries {
+            let query = Query::parse(query).unwrap();
+            let options = || query.find_options(SortOrder::Newest, 0, 50);
+
+            // the order in the page differs between the backends.
+            let ids = |x: FindResult| {
+                let mut ids = x.meigens.iter().map(|x| x.id).collect::<Vec<_>>();
+                ids.sort_unstable();
+                ids
+            };
+            let expected = ids(memory.find(options()).await.unwrap());
+            let actual = ids(mongo.find(options()).await.unwrap());
+
+            assert_eq!(actual, expected, "{:?}", query);
+        }
+
+        for prefix in ["かわ", "KAWA", "ｶﾜｴ", "some", "x"] {
+            assert_eq!(
+                mongo.authors_by_prefix(prefix, 10).await.unwrap(),
+                memory.authors_by_prefix(prefix, 10).await.unwrap(),
+                "{}",
+                prefix
+            );
+        }
+
+        drop_test_database(&url, &name).await;
     }
 }
@@ -5,10 +5,9 @@ use async_trait::async_trait;
 use reqwest::StatusCode;
 use serde::Deserialize;
 
-#[allow(dead_code)]
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize)]
 pub struct Credential {
-    user_id: String,
+    pub user_id: String,
 }
 
 pub enum Error {
@@ -88,7 +87,7 @@ pub struct AlwaysPass;
 impl Authenticator for AlwaysPass {
     async fn auth(&self, _token: &str) -> Result<Credential, Error> {
         Ok(Credential {
-            user_id: String::new(),
+            user_id: "0".into(),
         })
     }
 }
@@ -0,0 +1,18 @@
+//! opaque cursors for the keyset pagination.
+//! clients must not rely on the format, so that it can be changed later.
+
+const PREFIX: &str = "meigen:";
+
+pub(super) fn encode(id: u32) -> String {
+    base64::encode_config(format!("{}{}", PREFIX, id), base64::URL_SAFE_NO_PAD)
+}
+
+pub(super) fn decode(cursor: &str) -> Option<u32> {
+    let bytes = base64::decode_config(cursor, base64::URL_SAFE_NO_PAD).ok()?;
+
+    String::from_utf8(bytes)
+        .ok()?
+        .strip_prefix(PREFIX)?
+        .parse()
+        .ok()
+}
@@ -1,17 +1,19 @@
 use std::{
+    cmp::Reverse,
     convert::{TryFrom, TryInto},
     marker::PhantomData,
     sync::Arc,
 };
 
 use anyhow::Context as _;
+use chrono::{DateTime, Utc};
 use juniper::{
-    graphql_object, EmptyMutation, EmptySubscription, FieldError, FieldResult, GraphQLInputObject,
-    GraphQLObject, Value,
+    graphql_object, EmptySubscription, FieldError, FieldResult, GraphQLInputObject, GraphQLObject,
+    Value,
 };
 
-use super::CustomError;
-use crate::{db::MeigenDatabase, model, Synced};
+use super::{auth::Credential, CustomError};
+use crate::{db::MeigenDatabase, model, permission::Permissions, Synced};
 
 #[derive(GraphQLObject)]
 #[graphql(description = "A great sentence someone created via Discord Bot")]
@@ -20,6 +22,47 @@ struct Meigen {
     pub author: String,
     pub content: String,
     pub loved_user_id: Vec<String>,
+    #[graphql(description = "RFC 3339 formatted datetime")]
+    pub created_at: Option<String>,
+    pub created_by: Option<String>,
+    #[graphql(description = "RFC 3339 formatted datetime")]
+    pub updated_at: Option<String>,
+    #[graphql(description = "The Discord message this meigen was quoted from")]
+    pub source: Option<MeigenSource>,
+}
+
+#[derive(GraphQLObject)]
+struct MeigenSource {
+    #[graphql(description = "null if the message was sent in DM")]
+    pub guild_id: Option<String>,
+    pub channel_id: String,
+    pub message_id: String,
+}
+
+impl From<model::MeigenSource> for MeigenSource {
+    fn from(s: model::MeigenSource) -> Self {
+        Self {
+            guild_id: s.guild_id.map(|x| x.to_string()),
+            channel_id: s.channel_id.to_string(),
+            message_id: s.message_id.to_string(),
+        }
+    }
+}
+
+impl TryFrom<MeigenSource> for model::MeigenSource {
+    type Error = anyhow::Error;
+
+    fn try_from(s: MeigenSource) -> Result<Self, Self::Error> {
+        Ok(Self {
+            guild_id: s
+                .guild_id
+                .map(|x| x.parse())
+                .transpose()
+                .context("could not parse guild_id")?,
+            channel_id: s.channel_id.parse().context("could not parse channel_id")?,
+            message_id: s.message_id.parse().context("could not parse message_id")?,
+        })
+    }
 }
 
 impl From<model::Meigen> for Meigen {
@@ -29,6 +72,10 @@ impl From<model::Meigen> for Meigen {
             author: m.author,
             content: m.content,
             loved_user_id: m.loved_user_id.iter().map(|x| x.to_string()).collect(),
+            created_at: m.created_at.map(|x| x.to_rfc3339()),
+            created_by: m.created_by.map(|x| x.to_string()),
+            updated_at: m.updated_at.map(|x| x.to_rfc3339()),
+            source: m.source.map(From::from),
         }
     }
 }
@@ -44,37 +91,160 @@ impl TryFrom<Meigen> for model::Meigen {
             .collect::<Result<Vec<u64>, _>>()
             .context("could not parse loved_user_id")?;
 
+        let parse_time = |x: Option<String>| {
+            x.map(|x| DateTime::parse_from_rfc3339(&x).map(|x| x.with_timezone(&Utc)))
+                .transpose()
+        };
+
         Ok(Self {
             id: m.id as u32,
             author: m.author,
             content: m.content,
             loved_user_id,
+            created_at: parse_time(m.created_at).context("could not parse created_at")?,
+            created_by: m
+                .created_by
+                .map(|x| x.parse())
+                .transpose()
+                .context("could not parse created_by")?,
+            updated_at: parse_time(m.updated_at).context("could not parse updated_at")?,
+            source: m.source.map(TryFrom::try_from).transpose()?,
         })
     }
 }
 
+#[derive(GraphQLObject)]
+#[graphql(description = "Total loves of meigens by an author")]
+struct AuthorStats {
+    pub author: String,
+    pub loves: i32,
+    pub meigens: i32,
+}
+
+impl From<model::AuthorStats> for AuthorStats {
+    fn from(s: model::AuthorStats) -> Self {
+        Self {
+            author: s.author,
+            loves: s.loves as i32,
+            meigens: s.meigens as i32,
+        }
+    }
+}
+
 #[derive(GraphQLInputObject)]
 struct RandomRequest {
     count: Option<i32>,
 }
 
+#[derive(GraphQLObject)]
+#[graphql(description = "A page of search results")]
+struct SearchResult {
+    pub meigens: Vec<Meigen>,
+    #[graphql(description = "How many meigens matched in all pages")]
+    pub total: i32,
+    #[graphql(description = "1-based page number")]
+    pub page: i32,
+    pub total_pages: i32,
+    pub has_next: bool,
+    pub has_prev: bool,
+    #[graphql(description = "Pass this as `after` to get the next page")]
+    pub next_page_token: Option<String>,
+    #[graphql(description = "Pass this as `before` to get the previous page")]
+    pub prev_page_token: Option<String>,
+}
+
+impl From<super::SearchResponse> for SearchResult {
+    fn from(r: super::SearchResponse) -> Self {
+        Self {
+            meigens: r.meigens.into_iter().map(From::from).collect(),
+            total: r.total as i32,
+            page: r.page as i32,
+            total_pages: r.total_pages as i32,
+            has_next: r.has_next,
+            has_prev: r.has_prev,
+            next_page_token: r.next_page_token,
+            prev_page_token: r.prev_page_token,
+        }
+    }
+}
+
+#[derive(GraphQLObject)]
+struct MeigenEdge {
+    pub node: Meigen,
+    pub cursor: String,
+}
+
+#[derive(GraphQLObject)]
+struct PageInfo {
+    pub has_next_page: bool,
+    pub has_previous_page: bool,
+    pub start_cursor: Option<String>,
+    pub end_cursor: Option<String>,
+}
+
+#[derive(GraphQLObject)]
+#[graphql(description = "Relay-style connection of meigens, newer first")]
+struct MeigenConnection {
+    pub edges: Vec<MeigenEdge>,
+    pub page_info: PageInfo,
+    pub total_count: i32,
+}
+
+impl From<super::SearchResponse> for MeigenConnection {
+    fn from(mut r: super::SearchResponse) -> Self {
+        // some databases return them in ascending order.
+        r.meigens.sort_by_key(|x| Reverse(x.id));
+
+        let edges = r
+            .meigens
+            .into_iter()
+            .map(|x| MeigenEdge {
+                cursor: super::cursor::encode(x.id),
+                node: x.into(),
+            })
+            .collect::<Vec<_>>();
+
+        let page_info = PageInfo {
+            has_next_page: r.has_next,
+            has_previous_page: r.has_prev,
+            start_cursor: edges.first().map(|x| x.cursor.clone()),
+            end_cursor: edges.last().map(|x| x.cursor.clone()),
+        };
+
+        Self {
+            edges,
+            page_info,
+            total_count: r.total as i32,
+        }
+    }
+}
+
 #[derive(GraphQLInputObject)]
 struct SearchRequest {
     offset: Option<i32>,
+    #[graphql(description = "1-based. takes priority over offset")]
+    page: Option<i32>,
     limit: Option<i32>,
     author: Option<String>,
     content: Option<String>,
+    #[graphql(description = "e.g. `author:foo loves:>=3 -bar`")]
+    query: Option<String>,
+    #[graphql(description = "nextPageToken of the previous result")]
+    after: Option<String>,
+    #[graphql(description = "prevPageToken of the previous result")]
+    before: Option<String>,
 }
 
-type Schema<D> =
-    juniper::RootNode<'static, Query<D>, EmptyMutation<Context<D>>, EmptySubscription<Context<D>>>;
+type Schema<D> = juniper::RootNode<'static, Query<D>, Mutation<D>, EmptySubscription<Context<D>>>;
 
 pub(crate) fn schema<D: MeigenDatabase>() -> Schema<D> {
-    Schema::new(Query::new(), EmptyMutation::new(), EmptySubscription::new())
+    Schema::new(Query::new(), Mutation::new(), EmptySubscription::new())
 }
 
 pub(crate) struct Context<D> {
     pub(crate) db: Synced<D>,
+    pub(crate) permissions: Arc<Permissions>,
+    pub(crate) credential: Credential,
 }
 
 // #[derive(Clone)] requires D: Clone which is not actually needed.
@@ -82,6 +252,8 @@ impl<D> Clone for Context<D> {
     fn clone(&self) -> Self {
         Self {
             db: Arc::clone(&self.db),
+            permissions: Arc::clone(&self.permissions),
+            credential: self.credential.clone(),
         }
     }
 }
@@ -100,6 +272,18 @@ impl<D> Query<D> {
     }
 }
 
+pub(crate) struct Mutation<D> {
+    _phantom_db: PhantomData<fn() -> D>,
+}
+
+impl<D> Mutation<D> {
+    fn new() -> Self {
+        Self {
+            _phantom_db: PhantomData,
+        }
+    }
+}
+
 fn into_field_error(e: CustomError) -> FieldError {
     FieldError::new(e.describe(), Value::Null)
 }
@@ -135,17 +319,173 @@ impl<D: MeigenDatabase> Query<D> {
         }
     }
 
-    async fn search(context: &Context<D>, option: SearchRequest) -> FieldResult<Vec<Meigen>> {
+    async fn search(context: &Context<D>, option: SearchRequest) -> FieldResult<SearchResult> {
         let option = super::SearchRequest {
             offset: convert_opt_int!(option.offset, "offset"),
+            page: convert_opt_int!(option.page, "page"),
             limit: convert_opt_int!(option.limit, "limit"),
             author: option.author,
             content: option.content,
+            query: option.query,
+            after: option.after,
+            before: option.before,
         };
 
         match super::search(option, Arc::clone(&context.db)).await {
+            Ok(v) => Ok(v.into()),
+            Err(e) => Err(into_field_error(e)),
+        }
+    }
+
+    async fn meigens(
+        context: &Context<D>,
+        query: Option<String>,
+        first: Option<i32>,
+        after: Option<String>,
+        last: Option<i32>,
+        before: Option<String>,
+    ) -> FieldResult<MeigenConnection> {
+        if first.is_some() && last.is_some() {
+            return Err(FieldError::new(
+                "first and last cannot be used together",
+                Value::Null,
+            ));
+        }
+
+        // `last` without `before` means the oldest ones, which come just after the id 0.
+        let before = match (last, before) {
+            (Some(_), None) => Some(super::cursor::encode(0)),
+            (_, before) => before,
+        };
+
+        let option = super::SearchRequest {
+            offset: None,
+            page: None,
+            limit: convert_opt_int!(first.or(last), "first or last"),
+            author: None,
+            content: None,
+            query,
+            after,
+            before,
+        };
+
+        match super::search(option, Arc::clone(&context.db)).await {
+            Ok(v) => Ok(v.into()),
+            Err(e) => Err(into_field_error(e)),
+        }
+    }
+
+    async fn ranking(
+        context: &Context<D>,
+        offset: Option<i32>,
+        limit: Option<i32>,
+    ) -> FieldResult<Vec<Meigen>> {
+        let option = super::RankingRequest {
+            offset: convert_opt_int!(offset, "offset"),
+            page: None,
+            limit: convert_opt_int!(limit, "limit"),
+        };
+
+        match super::ranking(option, Arc::clone(&context.db)).await {
+            Ok(v) => Ok(v.into_iter().map(From::from).collect()),
+            Err(e) => Err(into_field_error(e)),
+        }
+    }
+
+    async fn top_authors(
+        context: &Context<D>,
+        offset: Option<i32>,
+        limit: Option<i32>,
+    ) -> FieldResult<Vec<AuthorStats>> {
+        let option = super::RankingRequest {
+            offset: convert_opt_int!(offset, "offset"),
+            page: None,
+            limit: convert_opt_int!(limit, "limit"),
+        };
+
+        match super::top_authors(option, Arc::clone(&context.db)).await {
             Ok(v) => Ok(v.into_iter().map(From::from).collect()),
             Err(e) => Err(into_field_error(e)),
         }
     }
 }
+
+#[graphql_object(context = Context<D>)]
+impl<D: MeigenDatabase> Mutation<D> {
+    async fn make(context: &Context<D>, author: String, content: String) -> FieldResult<Meigen> {
+        let request = super::MakeRequest { author, content };
+        let credential = context.credential.clone();
+
+        match super::make(
+            request,
+            credential,
+            &context.permissions,
+            Arc::clone(&context.db),
+        )
+        .await
+        {
+            Ok(v) => Ok(v.into()),
+            Err(e) => Err(into_field_error(e)),
+        }
+    }
+
+    async fn delete(context: &Context<D>, id: i32) -> FieldResult<bool> {
+        let credential = context.credential.clone();
+
+        let permissions = &context.permissions;
+
+        match super::delete(id as u32, credential, permissions, Arc::clone(&context.db)).await {
+            Ok(()) => Ok(true),
+            Err(e) => Err(into_field_error(e)),
+        }
+    }
+
+    async fn edit(
+        context: &Context<D>,
+        id: i32,
+        author: Option<String>,
+        content: Option<String>,
+    ) -> FieldResult<Meigen> {
+        let request = super::EditRequest { author, content };
+        let credential = context.credential.clone();
+        let permissions = &context.permissions;
+        let db = Arc::clone(&context.db);
+
+        match super::edit(id as u32, request, credential, permissions, db).await {
+            Ok(v) => Ok(v.into()),
+            Err(e) => Err(into_field_error(e)),
+        }
+    }
+
+    async fn love(context: &Context<D>, id: i32) -> FieldResult<Meigen> {
+        let credential = context.credential.clone();
+
+        match super::love(
+            id as u32,
+            credential,
+            &context.permissions,
+            Arc::clone(&context.db),
+        )
+        .await
+        {
+            Ok(v) => Ok(v.into()),
+            Err(e) => Err(into_field_error(e)),
+        }
+    }
+
+    async fn unlove(context: &Context<D>, id: i32) -> FieldResult<Meigen> {
+        let credential = context.credential.clone();
+
+        match super::unlove(
+            id as u32,
+            credential,
+            &context.permissions,
+            Arc::clone(&context.db),
+        )
+        .await
+        {
+            Ok(v) => Ok(v.into()),
+            Err(e) => Err(into_field_error(e)),
+        }
+    }
+}
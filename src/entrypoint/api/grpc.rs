@@ -4,16 +4,19 @@ use anyhow::Context as _;
 use async_trait::async_trait;
 use protobuf::{
     meigen_api_server::{MeigenApi, MeigenApiServer},
-    GetRequest, GetResponse, RandomRequest, RandomResponse, SearchRequest, SearchResponse,
+    DeleteRequest, DeleteResponse, EditRequest, EditResponse, GetRequest, GetResponse, LoveRequest,
+    LoveResponse, MakeRequest, MakeResponse, RandomRequest, RandomResponse, RankingRequest,
+    RankingResponse, SearchRequest, SearchResponse, TopAuthorsRequest, TopAuthorsResponse,
+    UnloveRequest, UnloveResponse,
 };
 use tokio::sync::RwLock;
 use tonic::{transport::Server, Code, Request, Response, Status};
 
 use super::{
-    auth::{self, Authenticator},
+    auth::{self, Authenticator, Credential},
     CustomError,
 };
-use crate::{db::MeigenDatabase, Synced};
+use crate::{db::MeigenDatabase, permission::Permissions, Synced};
 
 mod protobuf {
     tonic::include_proto!("meigen_api");
@@ -24,6 +27,31 @@ mod protobuf {
                 id: v.id,
                 author: v.author,
                 content: v.content,
+                loved_user_id: v.loved_user_id,
+                created_at: v.created_at.map(|x| x.timestamp_millis()),
+                created_by: v.created_by,
+                updated_at: v.updated_at.map(|x| x.timestamp_millis()),
+                source: v.source.map(From::from),
+            }
+        }
+    }
+
+    impl From<crate::model::MeigenSource> for MeigenSource {
+        fn from(v: crate::model::MeigenSource) -> Self {
+            Self {
+                guild_id: v.guild_id,
+                channel_id: v.channel_id,
+                message_id: v.message_id,
+            }
+        }
+    }
+
+    impl From<crate::model::AuthorStats> for AuthorStats {
+        fn from(v: crate::model::AuthorStats) -> Self {
+            Self {
+                author: v.author,
+                loves: v.loves,
+                meigens: v.meigens,
             }
         }
     }
@@ -32,6 +60,7 @@ mod protobuf {
 pub struct GrpcServer<A, D> {
     auth: A,
     db: Synced<D>,
+    permissions: Permissions,
 }
 
 impl<A, D> GrpcServer<A, D>
@@ -39,10 +68,11 @@ where
     A: Authenticator,
     D: MeigenDatabase,
 {
-    pub fn new(db: D, auth: A) -> Self {
+    pub fn new(db: D, auth: A, permissions: Permissions) -> Self {
         Self {
             db: Arc::new(RwLock::new(db)),
             auth,
+            permissions,
         }
     }
 
@@ -57,7 +87,7 @@ where
             .context("failed to start server")
     }
 
-    async fn auth<T>(&self, request: &tonic::Request<T>) -> Result<(), Status> {
+    async fn auth<T>(&self, request: &tonic::Request<T>) -> Result<Credential, Status> {
         let token = request
             .metadata()
             .get("gauth-token")
@@ -69,7 +99,7 @@ where
         };
 
         match self.auth.auth(token_str).await {
-            Ok(_) => Ok(()),
+            Ok(c) => Ok(c),
 
             Err(auth::Error::Internal(e)) => {
                 tracing::error!("internal error: {:#?}", e);
@@ -141,16 +171,189 @@ where
             offset: request.offset,
             author: request.author,
             content: request.content,
+            query: request.query,
+            page: request.page,
+            after: request.page_token,
+            before: None,
         };
 
         let result = super::search(request, Arc::clone(&self.db))
+            .await
+            .map_err(into_status)?;
+
+        Ok(Response::new(SearchResponse {
+            meigen: result.meigens.into_iter().map(From::from).collect(),
+            total: result.total,
+            page: result.page,
+            total_pages: result.total_pages,
+            has_next: result.has_next,
+            has_prev: result.has_prev,
+            next_page_token: result.next_page_token.unwrap_or_default(),
+        }))
+    }
+
+    async fn ranking(
+        &self,
+        request: Request<RankingRequest>,
+    ) -> Result<Response<RankingResponse>, Status> {
+        self.auth(&request).await?;
+
+        let request = request.into_inner();
+
+        let limit: Option<u8> = match request.limit {
+            Some(t) => Some(
+                t.try_into()
+                    .map_err(|_| Status::invalid_argument("limit option is too big"))?,
+            ),
+            None => None,
+        };
+
+        let request = super::RankingRequest {
+            offset: request.offset,
+            page: None,
+            limit,
+        };
+
+        let result = super::ranking(request, Arc::clone(&self.db))
+            .await
+            .map_err(into_status)?
+            .into_iter()
+            .map(From::from)
+            .collect();
+
+        Ok(Response::new(RankingResponse { meigen: result }))
+    }
+
+    async fn top_authors(
+        &self,
+        request: Request<TopAuthorsRequest>,
+    ) -> Result<Response<TopAuthorsResponse>, Status> {
+        self.auth(&request).await?;
+
+        let request = request.into_inner();
+
+        let limit: Option<u8> = match request.limit {
+            Some(t) => Some(
+                t.try_into()
+                    .map_err(|_| Status::invalid_argument("limit option is too big"))?,
+            ),
+            None => None,
+        };
+
+        let request = super::RankingRequest {
+            offset: request.offset,
+            page: None,
+            limit,
+        };
+
+        let result = super::top_authors(request, Arc::clone(&self.db))
             .await
             .map_err(into_status)?
             .into_iter()
             .map(From::from)
             .collect();
 
-        Ok(Response::new(SearchResponse { meigen: result }))
+        Ok(Response::new(TopAuthorsResponse {
+            author_stats: result,
+        }))
+    }
+
+    async fn make(&self, request: Request<MakeRequest>) -> Result<Response<MakeResponse>, Status> {
+        let credential = self.auth(&request).await?;
+
+        let request = request.into_inner();
+        let request = super::MakeRequest {
+            author: request.author,
+            content: request.content,
+        };
+
+        let result = super::make(request, credential, &self.permissions, Arc::clone(&self.db))
+            .await
+            .map_err(into_status)?;
+
+        Ok(Response::new(MakeResponse {
+            meigen: Some(result.into()),
+        }))
+    }
+
+    async fn delete(
+        &self,
+        request: Request<DeleteRequest>,
+    ) -> Result<Response<DeleteResponse>, Status> {
+        let credential = self.auth(&request).await?;
+
+        super::delete(
+            request.into_inner().id,
+            credential,
+            &self.permissions,
+            Arc::clone(&self.db),
+        )
+        .await
+        .map_err(into_status)?;
+
+        Ok(Response::new(DeleteResponse {}))
+    }
+
+    async fn edit(&self, request: Request<EditRequest>) -> Result<Response<EditResponse>, Status> {
+        let credential = self.auth(&request).await?;
+
+        let request = request.into_inner();
+        let id = request.id;
+        let request = super::EditRequest {
+            author: request.author,
+            content: request.content,
+        };
+
+        let result = super::edit(
+            id,
+            request,
+            credential,
+            &self.permissions,
+            Arc::clone(&self.db),
+        )
+        .await
+        .map_err(into_status)?;
+
+        Ok(Response::new(EditResponse {
+            meigen: Some(result.into()),
+        }))
+    }
+
+    async fn love(&self, request: Request<LoveRequest>) -> Result<Response<LoveResponse>, Status> {
+        let credential = self.auth(&request).await?;
+
+        let result = super::love(
+            request.into_inner().id,
+            credential,
+            &self.permissions,
+            Arc::clone(&self.db),
+        )
+        .await
+        .map_err(into_status)?;
+
+        Ok(Response::new(LoveResponse {
+            meigen: Some(result.into()),
+        }))
+    }
+
+    async fn unlove(
+        &self,
+        request: Request<UnloveRequest>,
+    ) -> Result<Response<UnloveResponse>, Status> {
+        let credential = self.auth(&request).await?;
+
+        let result = super::unlove(
+            request.into_inner().id,
+            credential,
+            &self.permissions,
+            Arc::clone(&self.db),
+        )
+        .await
+        .map_err(into_status)?;
+
+        Ok(Response::new(UnloveResponse {
+            meigen: Some(result.into()),
+        }))
     }
 }
 
@@ -162,9 +365,15 @@ fn into_status(c: CustomError) -> Status {
         }
 
         CustomError::SearchWordLengthLimitExceeded => Code::InvalidArgument,
+        CustomError::InvalidQuery => Code::InvalidArgument,
+        CustomError::InvalidCursor => Code::InvalidArgument,
+        CustomError::InvalidArgument => Code::InvalidArgument,
+        CustomError::MeigenLengthLimitExceeded => Code::InvalidArgument,
         CustomError::FetchLimitExceeded => Code::InvalidArgument,
         CustomError::TooBigOffset => Code::OutOfRange,
         CustomError::Authentication => Code::Unauthenticated,
+        CustomError::Forbidden => Code::PermissionDenied,
+        CustomError::NotFound => Code::NotFound,
     };
 
     Status::new(code, c.describe())
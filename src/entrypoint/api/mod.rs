@@ -1,4 +1,5 @@
 pub mod auth;
+mod cursor;
 
 #[cfg(feature = "api_http")]
 pub mod warp;
@@ -10,24 +11,36 @@ pub mod grpc;
 mod graphql;
 
 use anyhow::{Context as _, Result};
-use rand::{prelude::SmallRng, Rng, SeedableRng};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
+use self::auth::Credential;
 use crate::{
-    db::{FindOptions, MeigenDatabase},
-    model::Meigen,
+    command::{
+        registry::{self, Limit},
+        Command, CommandError, CommandOutput,
+    },
+    db::{FindResult, MeigenDatabase, SortOrder},
+    model::{AuthorStats, Meigen},
+    permission::{Actor, Permissions},
+    query::{Query, QUERY_LENGTH_LIMIT},
     Synced,
 };
 
 const SEARCH_STRING_LENGTH_LIMIT: usize = 100;
-const MAX_FETCH_COUNT: usize = 50;
+const MAX_OFFSET: u32 = 1000;
 
 #[derive(Debug)]
 enum CustomError {
     Internal(anyhow::Error),
     Authentication,
+    Forbidden,
+    NotFound,
     FetchLimitExceeded,
     SearchWordLengthLimitExceeded,
+    InvalidQuery,
+    InvalidCursor,
+    InvalidArgument,
+    MeigenLengthLimitExceeded,
     TooBigOffset,
 }
 
@@ -37,99 +50,157 @@ impl CustomError {
             CustomError::Internal(_) => "internal server error",
             CustomError::FetchLimitExceeded => "attempted to get too many meigens",
             CustomError::SearchWordLengthLimitExceeded => "search keyword is too long",
-            CustomError::TooBigOffset => "offset is too big",
+            CustomError::InvalidQuery => "search query is malformed",
+            CustomError::InvalidCursor => "cursor is malformed",
+            CustomError::InvalidArgument => "request has invalid arguments",
+            CustomError::MeigenLengthLimitExceeded => "meigen is too long",
+            CustomError::TooBigOffset => "offset is too big. use cursors instead",
             CustomError::Authentication => "unauthorized",
+            CustomError::Forbidden => "you are not allowed to do this operation",
+            CustomError::NotFound => "meigen was not found",
         }
     }
 }
 
-async fn get(id: u32, db: Synced<impl MeigenDatabase>) -> Result<Option<Meigen>, CustomError> {
-    db.read()
-        .await
-        .load(id)
-        .await
-        .map_err(CustomError::Internal)
-}
+fn actor(credential: &Credential) -> Result<Actor, CustomError> {
+    let user_id = credential
+        .user_id
+        .parse()
+        .with_context(|| {
+            format!(
+                "authenticator returned invalid user id: {}",
+                credential.user_id
+            )
+        })
+        .map_err(CustomError::Internal)?;
 
-#[derive(Deserialize)]
-struct RandomRequest {
-    count: Option<usize>,
+    // API users don't belong to any guild, so they have no roles.
+    Ok(Actor {
+        user_id,
+        role_ids: vec![],
+    })
 }
 
-async fn random(
-    body: RandomRequest,
-    db: Synced<impl MeigenDatabase>,
-) -> Result<Vec<Meigen>, CustomError> {
-    let count = body.count.unwrap_or(1);
-    let max = db
-        .read()
-        .await
-        .get_current_id()
-        .await
-        .context("failed to get current id")
-        .map_err(CustomError::Internal)? as usize;
+/// the bounds are the same as the chat commands, but out of range values are rejected instead of clamped.
+fn check_count(limit: &Limit, count: Option<usize>) -> Result<u8, CustomError> {
+    let count = count.unwrap_or(limit.default as usize);
 
-    if count > MAX_FETCH_COUNT || count > max {
+    if count > limit.max as usize {
         return Err(CustomError::FetchLimitExceeded);
     }
 
-    let mut rng = SmallRng::from_rng(&mut rand::thread_rng()).unwrap();
-
-    let mut meigens = Vec::<Meigen>::with_capacity(count);
+    if count < limit.min as usize {
+        return Err(CustomError::InvalidArgument);
+    }
 
-    while meigens.len() != count {
-        let want = count - meigens.len();
-        let mut try_fetch = Vec::with_capacity(want);
+    Ok(count as u8)
+}
 
-        loop {
-            let new_id_candidate = rng.gen_range(1..=max as u32);
+/// the commands page by page number. offset is accepted only when it's at the start of a page.
+fn page_of(page: Option<u32>, offset: Option<u32>, limit: u8) -> Result<u32, CustomError> {
+    if let Some(page) = page {
+        return Ok(page.max(1));
+    }
 
-            if try_fetch.contains(&new_id_candidate) {
-                continue;
-            }
+    let offset = offset.unwrap_or(0);
 
-            if meigens.iter().any(|x| x.id == new_id_candidate) {
-                continue;
-            }
+    if offset > MAX_OFFSET {
+        return Err(CustomError::TooBigOffset);
+    }
 
-            try_fetch.push(new_id_candidate);
-            if try_fetch.len() == want {
-                break;
-            }
-        }
+    if !offset.is_multiple_of(limit as u32) {
+        return Err(CustomError::InvalidArgument);
+    }
 
-        let mut fetched = db
-            .read()
-            .await
-            .load_bulk(&try_fetch)
-            .await
-            .context("failed to failed to bulk load")
-            .map_err(CustomError::Internal)?;
+    Ok(offset / limit as u32 + 1)
+}
 
-        meigens.append(&mut fetched);
+async fn get(id: u32, db: Synced<impl MeigenDatabase>) -> Result<Option<Meigen>, CustomError> {
+    match read(Command::Id(id), db).await {
+        Ok(output) => the_meigen(output).map(Some),
+        Err(CustomError::NotFound) => Ok(None),
+        Err(e) => Err(e),
     }
+}
 
-    Ok(meigens)
+#[derive(Deserialize)]
+struct RandomRequest {
+    count: Option<usize>,
+}
+
+async fn random(
+    body: RandomRequest,
+    db: Synced<impl MeigenDatabase>,
+) -> Result<Vec<Meigen>, CustomError> {
+    let count = check_count(&registry::RANDOM_COUNT, body.count)?;
+
+    read(Command::Random { count: Some(count) }, db)
+        .await
+        .map(|x| x.meigens)
 }
 
 #[derive(Deserialize)]
 struct SearchRequest {
     offset: Option<u32>,
+    /// 1-based. takes priority over offset.
+    page: Option<u32>,
     limit: Option<u8>,
     author: Option<String>,
     content: Option<String>,
+    /// see `crate::query`. author and content above take priority over the ones in the query.
+    query: Option<String>,
+    /// `next_page_token` of the previous response. offset and page are counted from here.
+    after: Option<String>,
+    /// `prev_page_token` of the previous response.
+    before: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SearchResponse {
+    meigens: Vec<Meigen>,
+    /// how many meigens matched in all pages.
+    total: u32,
+    /// 1-based.
+    page: u32,
+    total_pages: u32,
+    has_next: bool,
+    has_prev: bool,
+    next_page_token: Option<String>,
+    prev_page_token: Option<String>,
+}
+
+impl From<FindResult> for SearchResponse {
+    fn from(r: FindResult) -> Self {
+        // the order of meigens depends on the database. newer pages have larger ids anyway.
+        let ids = || r.meigens.iter().map(|x| x.id);
+        let next_page_token = ids().min().filter(|_| r.has_next()).map(cursor::encode);
+        let prev_page_token = ids().max().filter(|_| r.has_prev()).map(cursor::encode);
+
+        Self {
+            next_page_token,
+            prev_page_token,
+            total: r.total,
+            page: r.page(),
+            total_pages: r.total_pages(),
+            has_next: r.has_next(),
+            has_prev: r.has_prev(),
+            meigens: r.meigens,
+        }
+    }
 }
 
 async fn search(
     body: SearchRequest,
     db: Synced<impl MeigenDatabase>,
-) -> Result<Vec<Meigen>, CustomError> {
-    let limit = body.limit.unwrap_or(5);
-    let offset = body.offset.unwrap_or(0);
-
-    if limit as usize > MAX_FETCH_COUNT {
-        return Err(CustomError::FetchLimitExceeded);
-    }
+) -> Result<SearchResponse, CustomError> {
+    let limit = check_count(&registry::LIST_COUNT, body.limit.map(usize::from))?;
+
+    let offset = match body.page {
+        Some(page) => (page.max(1) - 1)
+            .checked_mul(limit as u32)
+            .ok_or(CustomError::TooBigOffset)?,
+        None => body.offset.unwrap_or(0),
+    };
 
     let check_word_len = |x: &Option<String>| {
         x.as_ref()
@@ -145,32 +216,248 @@ async fn search(
         return Err(CustomError::SearchWordLengthLimitExceeded);
     }
 
-    if offset > 0 {
-        let max = db
-            .read()
-            .await
-            .get_current_id()
-            .await
-            .context("failed to get current id")
-            .map_err(CustomError::Internal)?;
+    let query_len = body.query.as_ref().map_or(0, |x| x.chars().count());
 
-        if offset > max {
-            return Err(CustomError::TooBigOffset);
-        }
+    if query_len > QUERY_LENGTH_LIMIT {
+        return Err(CustomError::SearchWordLengthLimitExceeded);
     }
 
-    let list = db
+    let mut query = match body.query {
+        Some(ref q) => Query::parse(q).map_err(|_| CustomError::InvalidQuery)?,
+        None => Query::default(),
+    };
+
+    if body.author.is_some() {
+        query.author = body.author;
+    }
+
+    if body.content.is_some() {
+        query.content = body.content;
+    }
+
+    // skipping many documents is slow. clients should use cursors to go further.
+    if offset > MAX_OFFSET {
+        return Err(CustomError::TooBigOffset);
+    }
+
+    let decode_cursor = |x: Option<String>| {
+        x.map(|x| cursor::decode(&x).ok_or(CustomError::InvalidCursor))
+            .transpose()
+    };
+
+    let mut options = query.find_options(SortOrder::Newest, offset, limit);
+    // the results are newest first, so the page "after" a cursor is the older meigens.
+    options.older_than = decode_cursor(body.after)?;
+    options.newer_than = decode_cursor(body.before)?;
+
+    let result = db
         .read()
         .await
-        .find(FindOptions {
-            author: body.author.as_ref().map(|x| x as _),
-            content: body.content.as_ref().map(|x| x as _),
-            offset,
-            limit,
-        })
+        .find(options)
         .await
         .context("failed to find")
         .map_err(CustomError::Internal)?;
 
-    Ok(list)
+    Ok(result.into())
+}
+
+#[derive(Deserialize)]
+struct RankingRequest {
+    offset: Option<u32>,
+    /// 1-based. takes priority over offset.
+    page: Option<u32>,
+    limit: Option<u8>,
+}
+
+async fn ranking(
+    body: RankingRequest,
+    db: Synced<impl MeigenDatabase>,
+) -> Result<Vec<Meigen>, CustomError> {
+    let count = check_count(&registry::LIST_COUNT, body.limit.map(usize::from))?;
+    let page = page_of(body.page, body.offset, count)?;
+
+    let command = Command::Ranking {
+        count: Some(count),
+        page: Some(page),
+    };
+
+    read(command, db).await.map(|x| x.meigens)
+}
+
+async fn top_authors(
+    body: RankingRequest,
+    db: Synced<impl MeigenDatabase>,
+) -> Result<Vec<AuthorStats>, CustomError> {
+    let count = check_count(&registry::AUTHOR_RANKING_COUNT, body.limit.map(usize::from))?;
+    let page = page_of(body.page, body.offset, count)?;
+
+    let command = Command::AuthorRanking {
+        count: Some(count),
+        page: Some(page),
+    };
+
+    read(command, db).await.map(|x| x.authors)
+}
+
+/// runs the command shared with the chat frontends, and maps the errors caused by the user.
+async fn run(
+    command: Command,
+    actor: &Actor,
+    permissions: &Permissions,
+    db: Synced<impl MeigenDatabase>,
+) -> Result<CommandOutput, CustomError> {
+    let output = command
+        .run(db, permissions, actor)
+        .await
+        .map_err(CustomError::Internal)?;
+
+    match output.error {
+        // loving twice is not an error, so that clients can retry.
+        None | Some(CommandError::Conflict) => Ok(output),
+        Some(CommandError::NotFound) => Err(CustomError::NotFound),
+        Some(CommandError::Forbidden) => Err(CustomError::Forbidden),
+        Some(CommandError::TooLong) => Err(CustomError::MeigenLengthLimitExceeded),
+        Some(CommandError::InvalidArgument) => Err(CustomError::InvalidArgument),
+    }
+}
+
+/// runs the command which only reads. they don't depend on who asked, so nobody is privileged here.
+async fn read(
+    command: Command,
+    db: Synced<impl MeigenDatabase>,
+) -> Result<CommandOutput, CustomError> {
+    let anonymous = Actor {
+        user_id: 0,
+        role_ids: vec![],
+    };
+
+    run(command, &anonymous, &Permissions::default(), db).await
+}
+
+/// for the commands which reply with the meigen they operated on.
+fn the_meigen(output: CommandOutput) -> Result<Meigen, CustomError> {
+    output
+        .meigens
+        .into_iter()
+        .next()
+        .context("command replied without meigen")
+        .map_err(CustomError::Internal)
+}
+
+#[derive(Deserialize)]
+struct MakeRequest {
+    author: String,
+    content: String,
+}
+
+async fn make(
+    body: MakeRequest,
+    credential: Credential,
+    permissions: &Permissions,
+    db: Synced<impl MeigenDatabase>,
+) -> Result<Meigen, CustomError> {
+    let command = Command::Make {
+        author: body.author,
+        content: body.content,
+        source: None,
+    };
+
+    run(command, &actor(&credential)?, permissions, db)
+        .await
+        .and_then(the_meigen)
+}
+
+async fn delete(
+    id: u32,
+    credential: Credential,
+    permissions: &Permissions,
+    db: Synced<impl MeigenDatabase>,
+) -> Result<(), CustomError> {
+    run(Command::Delete(id), &actor(&credential)?, permissions, db)
+        .await
+        .map(drop)
+}
+
+async fn love(
+    id: u32,
+    credential: Credential,
+    permissions: &Permissions,
+    db: Synced<impl MeigenDatabase>,
+) -> Result<Meigen, CustomError> {
+    run(Command::Love(id), &actor(&credential)?, permissions, db)
+        .await
+        .and_then(the_meigen)
+}
+
+async fn unlove(
+    id: u32,
+    credential: Credential,
+    permissions: &Permissions,
+    db: Synced<impl MeigenDatabase>,
+) -> Result<Meigen, CustomError> {
+    run(Command::Unlove(id), &actor(&credential)?, permissions, db)
+        .await
+        .and_then(the_meigen)
+}
+
+#[derive(Deserialize)]
+struct EditRequest {
+    author: Option<String>,
+    content: Option<String>,
+}
+
+async fn edit(
+    id: u32,
+    body: EditRequest,
+    credential: Credential,
+    permissions: &Permissions,
+    db: Synced<impl MeigenDatabase>,
+) -> Result<Meigen, CustomError> {
+    let command = Command::Edit {
+        id,
+        author: body.author,
+        content: body.content,
+    };
+
+    run(command, &actor(&credential)?, permissions, db)
+        .await
+        .and_then(the_meigen)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_are_bounded_like_the_commands() {
+        let random = &registry::RANDOM_COUNT;
+
+        assert_eq!(check_count(random, None).unwrap(), 1);
+        assert_eq!(check_count(random, Some(5)).unwrap(), 5);
+        assert!(matches!(
+            check_count(random, Some(6)),
+            Err(CustomError::FetchLimitExceeded)
+        ));
+        assert!(matches!(
+            check_count(random, Some(0)),
+            Err(CustomError::InvalidArgument)
+        ));
+    }
+
+    #[test]
+    fn offsets_become_pages() {
+        assert_eq!(page_of(None, None, 5).unwrap(), 1);
+        assert_eq!(page_of(None, Some(10), 5).unwrap(), 3);
+        assert_eq!(page_of(Some(2), Some(10), 5).unwrap(), 2);
+        assert_eq!(page_of(Some(0), None, 5).unwrap(), 1);
+
+        assert!(matches!(
+            page_of(None, Some(7), 5),
+            Err(CustomError::InvalidArgument)
+        ));
+        assert!(matches!(
+            page_of(None, Some(MAX_OFFSET + 5), 5),
+            Err(CustomError::TooBigOffset)
+        ));
+    }
 }
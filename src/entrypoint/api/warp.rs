@@ -3,31 +3,45 @@ use std::{convert::Infallible, net::SocketAddr, sync::Arc};
 use anyhow::Result;
 use reqwest::StatusCode;
 use tokio::sync::RwLock;
-use warp::{filter::FilterBase, Filter, Rejection, Reply};
+use warp::{Filter, Rejection, Reply};
 
-use super::{auth::Authenticator, CustomError};
-use crate::{db::MeigenDatabase, Synced};
+use super::{
+    auth::{Authenticator, Credential},
+    CustomError,
+};
+use crate::{db::MeigenDatabase, permission::Permissions, Synced};
 
 pub struct HttpApiServer<D: MeigenDatabase, A: Authenticator> {
     db: Synced<D>,
     auth: A,
+    permissions: Arc<Permissions>,
 }
 
 impl<D: MeigenDatabase, A: Authenticator> HttpApiServer<D, A> {
-    pub fn new(db: D, auth: A) -> Self {
+    pub fn new(db: D, auth: A, permissions: Permissions) -> Self {
         Self {
             db: Arc::new(RwLock::new(db)),
             auth,
+            permissions: Arc::new(permissions),
         }
     }
 
     pub async fn start(self, ip: impl Into<SocketAddr>) {
-        let route = graphql(&self.auth, &self.db)
-            .or(get(&self.auth, &self.db))
+        let route = get(&self.auth, &self.db)
             .or(random(&self.auth, &self.db))
             .or(search(&self.auth, &self.db))
-            .recover(recover)
-            .with(warp::trace::request());
+            .or(ranking(&self.auth, &self.db))
+            .or(top_authors(&self.auth, &self.db))
+            .or(make(&self.auth, &self.db, &self.permissions))
+            .or(delete(&self.auth, &self.db, &self.permissions))
+            .or(edit(&self.auth, &self.db, &self.permissions))
+            .or(love(&self.auth, &self.db, &self.permissions))
+            .or(unlove(&self.auth, &self.db, &self.permissions));
+
+        #[cfg(feature = "api_graphql")]
+        let route = graphql(&self.auth, &self.db, &self.permissions).or(route);
+
+        let route = route.recover(recover).with(warp::trace::request());
 
         let ip = ip.into();
         tracing::info!("starting server at {}", ip);
@@ -40,24 +54,20 @@ impl<D: MeigenDatabase, A: Authenticator> HttpApiServer<D, A> {
 fn graphql(
     auth: &impl Authenticator,
     db: &Synced<impl MeigenDatabase>,
+    permissions: &Arc<Permissions>,
 ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
-    let ctx = super::graphql::Context { db: Arc::clone(db) };
-    let ctx = inject(ctx).map_err(warp::filter::Internal, |e| -> Rejection { match e {} });
-
-    warp::path!("v1" / "graphql")
-        .and(auth_filter(auth.clone()))
-        .and(juniper_warp::make_graphql_filter(
-            super::graphql::schema(),
-            ctx,
-        ))
-}
+    let db = Arc::clone(db);
+    let permissions = Arc::clone(permissions);
+    let ctx = credential_filter(auth.clone()).map(move |credential| super::graphql::Context {
+        db: Arc::clone(&db),
+        permissions: Arc::clone(&permissions),
+        credential,
+    });
 
-#[cfg(not(feature = "api_graphql"))]
-fn graphql(
-    auth: &impl Authenticator,
-    db: &Synced<impl MeigenDatabase>,
-) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
-    warp::any()
+    warp::path!("v1" / "graphql").and(juniper_warp::make_graphql_filter(
+        super::graphql::schema(),
+        ctx,
+    ))
 }
 
 fn get(
@@ -98,7 +108,8 @@ fn search(
     auth: &impl Authenticator,
     db: &Synced<impl MeigenDatabase>,
 ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
-    warp::path!("v1" / "random")
+    // this used to be served at GET v1/random, where `random` shadowed it and it was never reachable.
+    warp::path!("v1" / "search")
         .and(warp::get())
         .and(auth_filter(auth.clone()))
         .and(warp::query::query())
@@ -111,7 +122,150 @@ fn search(
         })
 }
 
-fn auth_filter<A: Authenticator>(auth: A) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+fn ranking(
+    auth: &impl Authenticator,
+    db: &Synced<impl MeigenDatabase>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path!("v1" / "ranking")
+        .and(warp::get())
+        .and(auth_filter(auth.clone()))
+        .and(warp::query::query())
+        .and(inject(Arc::clone(db)))
+        .and_then(|query, db| async {
+            match super::ranking(query, db).await {
+                Ok(t) => Ok(warp::reply::json(&t)),
+                Err(e) => Err(Rejection::from(e)),
+            }
+        })
+}
+
+fn top_authors(
+    auth: &impl Authenticator,
+    db: &Synced<impl MeigenDatabase>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path!("v1" / "ranking" / "authors")
+        .and(warp::get())
+        .and(auth_filter(auth.clone()))
+        .and(warp::query::query())
+        .and(inject(Arc::clone(db)))
+        .and_then(|query, db| async {
+            match super::top_authors(query, db).await {
+                Ok(t) => Ok(warp::reply::json(&t)),
+                Err(e) => Err(Rejection::from(e)),
+            }
+        })
+}
+
+// 16KB limit
+const MAKE_CONTENT_LENGTH_LIMIT: u64 = 1024 * 16;
+
+fn make(
+    auth: &impl Authenticator,
+    db: &Synced<impl MeigenDatabase>,
+    permissions: &Arc<Permissions>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path!("v1")
+        .and(warp::post())
+        .and(credential_filter(auth.clone()))
+        .and(warp::body::content_length_limit(MAKE_CONTENT_LENGTH_LIMIT))
+        .and(warp::body::json())
+        .and(inject(Arc::clone(permissions)))
+        .and(inject(Arc::clone(db)))
+        .and_then(
+            |credential, body, permissions: Arc<Permissions>, db| async move {
+                match super::make(body, credential, &permissions, db).await {
+                    Ok(t) => Ok(warp::reply::json(&t)),
+                    Err(e) => Err(Rejection::from(e)),
+                }
+            },
+        )
+}
+
+fn delete(
+    auth: &impl Authenticator,
+    db: &Synced<impl MeigenDatabase>,
+    permissions: &Arc<Permissions>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path!("v1" / u32)
+        .and(warp::delete())
+        .and(credential_filter(auth.clone()))
+        .and(inject(Arc::clone(permissions)))
+        .and(inject(Arc::clone(db)))
+        .and_then(
+            |id, credential, permissions: Arc<Permissions>, db| async move {
+                match super::delete(id, credential, &permissions, db).await {
+                    Ok(()) => Ok(warp::reply::json(&serde_json::json!({}))),
+                    Err(e) => Err(Rejection::from(e)),
+                }
+            },
+        )
+}
+
+fn edit(
+    auth: &impl Authenticator,
+    db: &Synced<impl MeigenDatabase>,
+    permissions: &Arc<Permissions>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path!("v1" / u32)
+        .and(warp::patch())
+        .and(credential_filter(auth.clone()))
+        .and(warp::body::content_length_limit(MAKE_CONTENT_LENGTH_LIMIT))
+        .and(warp::body::json())
+        .and(inject(Arc::clone(permissions)))
+        .and(inject(Arc::clone(db)))
+        .and_then(
+            |id, credential, body, permissions: Arc<Permissions>, db| async move {
+                match super::edit(id, body, credential, &permissions, db).await {
+                    Ok(t) => Ok(warp::reply::json(&t)),
+                    Err(e) => Err(Rejection::from(e)),
+                }
+            },
+        )
+}
+
+fn love(
+    auth: &impl Authenticator,
+    db: &Synced<impl MeigenDatabase>,
+    permissions: &Arc<Permissions>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path!("v1" / u32 / "love")
+        .and(warp::post())
+        .and(credential_filter(auth.clone()))
+        .and(inject(Arc::clone(permissions)))
+        .and(inject(Arc::clone(db)))
+        .and_then(
+            |id, credential, permissions: Arc<Permissions>, db| async move {
+                match super::love(id, credential, &permissions, db).await {
+                    Ok(t) => Ok(warp::reply::json(&t)),
+                    Err(e) => Err(Rejection::from(e)),
+                }
+            },
+        )
+}
+
+fn unlove(
+    auth: &impl Authenticator,
+    db: &Synced<impl MeigenDatabase>,
+    permissions: &Arc<Permissions>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path!("v1" / u32 / "love")
+        .and(warp::delete())
+        .and(credential_filter(auth.clone()))
+        .and(inject(Arc::clone(permissions)))
+        .and(inject(Arc::clone(db)))
+        .and_then(
+            |id, credential, permissions: Arc<Permissions>, db| async move {
+                match super::unlove(id, credential, &permissions, db).await {
+                    Ok(t) => Ok(warp::reply::json(&t)),
+                    Err(e) => Err(Rejection::from(e)),
+                }
+            },
+        )
+}
+
+fn credential_filter<A: Authenticator>(
+    auth: A,
+) -> impl Filter<Extract = (Credential,), Error = Rejection> + Clone {
     warp::header::header::<String>("gauth-token")
         .and(inject(auth))
         .and_then(|token: String, auth: A| async move {
@@ -123,8 +277,10 @@ fn auth_filter<A: Authenticator>(auth: A) -> impl Filter<Extract = (), Error = R
                 })
                 .map_err(Rejection::from)
         })
-        .map(|_| ())
-        .untuple_one()
+}
+
+fn auth_filter<A: Authenticator>(auth: A) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    credential_filter(auth).map(|_| ()).untuple_one()
 }
 
 fn inject<T>(t: T) -> impl Filter<Extract = (T,), Error = Infallible> + Send + Clone
@@ -156,10 +312,16 @@ async fn recover(r: Rejection) -> Result<impl warp::Reply, Rejection> {
         }
 
         CustomError::SearchWordLengthLimitExceeded => (StatusCode::BAD_REQUEST, ce.describe()),
+        CustomError::InvalidQuery => (StatusCode::BAD_REQUEST, ce.describe()),
+        CustomError::InvalidCursor => (StatusCode::BAD_REQUEST, ce.describe()),
+        CustomError::InvalidArgument => (StatusCode::BAD_REQUEST, ce.describe()),
+        CustomError::MeigenLengthLimitExceeded => (StatusCode::BAD_REQUEST, ce.describe()),
 
         CustomError::FetchLimitExceeded => (StatusCode::BAD_REQUEST, ce.describe()),
         CustomError::TooBigOffset => (StatusCode::BAD_REQUEST, ce.describe()),
         CustomError::Authentication => (StatusCode::UNAUTHORIZED, ce.describe()),
+        CustomError::Forbidden => (StatusCode::FORBIDDEN, ce.describe()),
+        CustomError::NotFound => (StatusCode::NOT_FOUND, ce.describe()),
     };
 
     Ok(warp::reply::with_status(
@@ -7,16 +7,31 @@ use std::{
 use anyhow::Result;
 use tokio::sync::RwLock;
 
-use crate::{command, db::MeigenDatabase, Synced};
+use crate::{
+    command::{
+        registry::{self, ParseError, Syntax, CONSOLE_PREFIX},
+        Command, CommandError, CommandOutput,
+    },
+    db::MeigenDatabase,
+    permission::{Actor, Permissions, CONSOLE_USER_ID},
+    Synced,
+};
 
 pub struct Console<D: MeigenDatabase> {
     db: Synced<D>,
+    permissions: Permissions,
+    actor: Actor,
 }
 
 impl<D: MeigenDatabase> Console<D> {
     pub fn new(db: D) -> Self {
         Self {
             db: Arc::new(RwLock::new(db)),
+            permissions: Permissions::console(),
+            actor: Actor {
+                user_id: CONSOLE_USER_ID,
+                role_ids: vec![],
+            },
         }
     }
 
@@ -30,8 +45,8 @@ impl<D: MeigenDatabase> Console<D> {
             });
 
             let begin = Instant::now();
-            if let Some(Ok(text)) = self.on_input(buf.trim()).await {
-                println!("{}", text);
+            if let Some(Ok(output)) = self.on_input(buf.trim()).await {
+                println!("{}", output.text);
             }
             println!("process took {}ms", begin.elapsed().as_millis());
 
@@ -39,59 +54,99 @@ impl<D: MeigenDatabase> Console<D> {
         }
     }
 
-    async fn on_input(&mut self, text: &str) -> Option<Result<String>> {
-        let mut splitted = text.split(' ');
+    async fn on_input(&mut self, text: &str) -> Option<Result<CommandOutput>> {
+        let mut splitted = text.splitn(2, ' ');
 
-        if splitted.next()? != "g!meigen" {
+        if splitted.next()? != CONSOLE_PREFIX {
             return None;
         }
 
-        let sub_command = splitted.next()?;
+        let words = splitted
+            .next()
+            .unwrap_or_default()
+            .split_whitespace()
+            .collect::<Vec<_>>();
+
+        let command = match parse(&words) {
+            Ok(c) => c,
+            Err(e) => return Some(Ok(CommandOutput::error(CommandError::InvalidArgument, e))),
+        };
+
+        Some(
+            command
+                .run(Arc::clone(&self.db), &self.permissions, &self.actor)
+                .await,
+        )
+    }
+}
 
-        match sub_command {
-            "gophersay" => {
-                let id = splitted.next()?.parse().ok()?;
-                Some(command::gophersay(Arc::clone(&self.db), id).await)
-            }
+/// parses the words after the prefix. returns the message for the user if they are wrong.
+fn parse(words: &[&str]) -> Result<Command, String> {
+    let (spec, used) = registry::find(words).ok_or_else(|| {
+        format!(
+            "コマンドが正しくありません。{} help でヘルプを表示します。",
+            CONSOLE_PREFIX
+        )
+    })?;
 
-            "search" => {
-                let sub = splitted.next()?;
-
-                match sub {
-                    "author" => Some(
-                        command::search_author(
-                            Arc::clone(&self.db),
-                            splitted.next()?,
-                            splitted.next().map(|x| x.parse()).transpose().ok()?,
-                            splitted.next().map(|x| x.parse()).transpose().ok()?,
-                        )
-                        .await,
-                    ),
-
-                    "content" => Some(
-                        command::search_content(
-                            Arc::clone(&self.db),
-                            splitted.next()?,
-                            splitted.next().map(|x| x.parse()).transpose().ok()?,
-                            splitted.next().map(|x| x.parse()).transpose().ok()?,
-                        )
-                        .await,
-                    ),
-                    _ => None,
-                }
-            }
+    let usage = || format!("使い方:\n{}", spec.usage(Syntax::Console));
 
-            // this can be written smarter with "or_patterns"
-            "make" | "help" | "id" | "list" | "random" | "status" | "delete" | "love"
-            | "unlove" => {
-                // TODO: support more command
-                unimplemented!(
-                    "currently {} handler on console is unimplemented",
-                    sub_command
-                )
-            }
+    if !spec.subcommands.is_empty() {
+        return Err(usage());
+    }
+
+    let args = spec.console_args(&words[used..]);
+    let get = |name: &str| {
+        args.iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v.as_str())
+    };
+
+    Command::parse(&words[..used], Syntax::Console, get).map_err(|e| match e {
+        ParseError::Invalid(msg) => msg,
+        ParseError::Missing(_) | ParseError::UnknownCommand => usage(),
+    })
+}
 
-            _ => None,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::SearchBy;
+
+    fn search(words: &[&str]) -> (String, Option<u8>, Option<u32>) {
+        match parse(words) {
+            Ok(Command::Search {
+                by: SearchBy::Author(author),
+                count,
+                page,
+            }) => (author, count, page),
+            _ => panic!("{:?} was not parsed as search author", words),
         }
     }
+
+    #[test]
+    fn options_after_the_rest_are_parsed() {
+        assert_eq!(
+            search(&["search", "author", "foo", "5", "2"]),
+            ("foo".to_string(), Some(5), Some(2))
+        );
+        assert_eq!(
+            search(&["search", "author", "foo", "bar", "5"]),
+            ("foo bar".to_string(), Some(5), None)
+        );
+        assert_eq!(
+            search(&["search", "author", "foo", "-", "3"]),
+            ("foo".to_string(), None, Some(3))
+        );
+        assert_eq!(
+            search(&["search", "author", "foo", "bar"]),
+            ("foo bar".to_string(), None, None)
+        );
+
+        // the rest keeps at least one word.
+        assert_eq!(
+            search(&["search", "author", "5"]),
+            ("5".to_string(), None, None)
+        );
+    }
 }
@@ -0,0 +1,215 @@
+use std::cmp::Reverse;
+
+use anyhow::{Context as _, Result};
+use serde_json::{json, Value};
+use warp::{
+    reject::{custom as custom_reject, Rejection},
+    reply::{json as reply_json, Json},
+};
+
+use super::{
+    interaction::try_parse,
+    model::{Request, RequestOption},
+    BadRequest,
+};
+use crate::{
+    db::{FindOptions, MeigenDatabase},
+    model::{HistoryKind, Meigen},
+    Synced,
+};
+
+// discord shows at most 25 choices.
+const CHOICES_LIMIT: usize = 25;
+// both name and value of a choice must be shorter than this.
+const CHOICE_LENGTH_LIMIT: usize = 100;
+const PREVIEW_LENGTH: usize = 30;
+
+pub(super) async fn on_autocomplete(
+    body: String,
+    db: Synced<impl MeigenDatabase>,
+) -> Result<Json, Rejection> {
+    let request = try_parse::<Request>(&body)?;
+
+    let focused = find_focused(&request.data.options).ok_or_else(|| {
+        tracing::info!("autocomplete request has no focused option");
+        custom_reject(BadRequest)
+    })?;
+
+    let typed = focused.value.as_deref().unwrap_or("").trim();
+
+    let choices = match focused.name.as_str() {
+        "author" => author_choices(db, typed).await,
+        // only the deleted meigens can be restored.
+        "id" if is_restore(&request.data.options) => deleted_id_choices(db, typed).await,
+        "id" => id_choices(db, typed).await,
+        _ => Ok(vec![]),
+    };
+
+    // suggestions are not essential. show nothing rather than an error.
+    let choices = choices.unwrap_or_else(|e| {
+        tracing::error!("failed to autocomplete: {:?}", e);
+        vec![]
+    });
+
+    Ok(reply_json(&json!({
+        // ApplicationCommandAutocompleteResult
+        "type": 8,
+        "data": {
+            "choices": choices,
+        }
+    })))
+}
+
+fn find_focused(options: &[RequestOption]) -> Option<&RequestOption> {
+    options.iter().find_map(|x| match x.options {
+        Some(ref children) => find_focused(children),
+        None if x.focused => Some(x),
+        None => None,
+    })
+}
+
+fn is_restore(options: &[RequestOption]) -> bool {
+    matches!(options.first(), Some(x) if x.name == "restore")
+}
+
+async fn author_choices(db: Synced<impl MeigenDatabase>, typed: &str) -> Result<Vec<Value>> {
+    let authors = db
+        .read()
+        .await
+        .authors_by_prefix(typed, CHOICES_LIMIT as _)
+        .await
+        .context("failed to get authors")?;
+
+    Ok(authors
+        .into_iter()
+        .filter(|x| x.chars().count() <= CHOICE_LENGTH_LIMIT)
+        .map(|x| json!({ "name": x, "value": x }))
+        .collect())
+}
+
+async fn id_choices(db: Synced<impl MeigenDatabase>, typed: &str) -> Result<Vec<Value>> {
+    let db = db.read().await;
+
+    let meigens = if let Ok(prefix) = typed.parse::<u32>() {
+        let current_id = db
+            .get_current_id()
+            .await
+            .context("failed to get current id")?;
+
+        let mut meigens = db
+            .load_bulk(&ids_starting_with(prefix, current_id))
+            .await
+            .context("failed to load meigens")?;
+
+        // the closest ones to the typed id come first.
+        meigens.sort_by_key(|x| x.id);
+        meigens
+    } else {
+        // not a number. let them find the meigen by its words, or show the newest ones.
+        let keywords = if typed.is_empty() {
+            vec![]
+        } else {
+            vec![typed]
+        };
+
+        let mut meigens = db
+            .find(FindOptions {
+                keywords,
+                limit: CHOICES_LIMIT as _,
+                ..Default::default()
+            })
+            .await
+            .context("failed to find")?
+            .meigens;
+
+        meigens.sort_by_key(|x| Reverse(x.id));
+        meigens
+    };
+
+    Ok(meigens
+        .iter()
+        .map(|x| json!({ "name": describe(x), "value": x.id }))
+        .collect())
+}
+
+async fn deleted_id_choices(db: Synced<impl MeigenDatabase>, typed: &str) -> Result<Vec<Value>> {
+    let db = db.read().await;
+
+    let ids = if let Ok(prefix) = typed.parse::<u32>() {
+        let current_id = db
+            .get_current_id()
+            .await
+            .context("failed to get current id")?;
+
+        ids_starting_with(prefix, current_id)
+    } else {
+        // the recently deleted ones. the restored ones among them are dropped by load_deleted_bulk.
+        db.recent_destructive_changes(u8::MAX)
+            .await
+            .context("failed to get recent changes")?
+            .into_iter()
+            .filter(|x| matches!(x.kind, HistoryKind::Delete))
+            .map(|x| x.meigen_id)
+            .collect()
+    };
+
+    let mut meigens = db
+        .load_deleted_bulk(&ids)
+        .await
+        .context("failed to load deleted meigens")?;
+
+    meigens.sort_by_key(|x| Reverse(x.id));
+
+    Ok(meigens
+        .iter()
+        .take(CHOICES_LIMIT)
+        .map(|x| json!({ "name": describe(x), "value": x.id }))
+        .collect())
+}
+
+/// e.g. 12 → 12, 120..=129, 1200..=1299, ... until CHOICES_LIMIT ids or `max` is reached.
+fn ids_starting_with(prefix: u32, max: u32) -> Vec<u32> {
+    let mut ids = vec![];
+
+    // no id starts with 0.
+    if prefix == 0 {
+        return ids;
+    }
+
+    let (mut start, mut end) = (prefix as u64, prefix as u64);
+
+    while start <= max as u64 && ids.len() < CHOICES_LIMIT {
+        let remaining = CHOICES_LIMIT - ids.len();
+        ids.extend(
+            (start..=end.min(max as u64))
+                .take(remaining)
+                .map(|x| x as u32),
+        );
+
+        start *= 10;
+        end = end * 10 + 9;
+    }
+
+    ids
+}
+
+fn describe(meigen: &Meigen) -> String {
+    let mut preview = meigen
+        .content
+        .chars()
+        .map(|c| if c.is_whitespace() { ' ' } else { c })
+        .take(PREVIEW_LENGTH)
+        .collect::<String>();
+
+    if meigen.content.chars().count() > PREVIEW_LENGTH {
+        preview.push('…');
+    }
+
+    let text = format!("#{} {}「{}」", meigen.id, meigen.author, preview);
+
+    // the author can be long too.
+    match text.char_indices().nth(CHOICE_LENGTH_LIMIT) {
+        Some((i, _)) => text[..i].to_string(),
+        None => text,
+    }
+}
@@ -0,0 +1,156 @@
+use anyhow::{Context as _, Result};
+use serde::Serialize;
+use serde_json::{
+    ser::{PrettyFormatter, Serializer},
+    Value,
+};
+
+use crate::command::registry::{
+    CommandSpec, OptionSpec, OptionType, COMMANDS, SLASH_COMMAND_DESCRIPTION, SLASH_COMMAND_NAME,
+};
+
+/// the message context menu command, handled by interaction::save_message.
+pub const SAVE_MESSAGE_COMMAND_NAME: &str = "Save as meigen";
+
+// guild install, user install
+const INTEGRATION_TYPES: &[u8] = &[0, 1];
+// guild, bot DM, private channel
+const CONTEXTS: &[u8] = &[0, 1, 2];
+
+/// see https://discord.com/developers/docs/interactions/application-commands#application-command-object
+#[derive(Serialize)]
+struct ApplicationCommand {
+    name: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<&'static str>,
+    /// 1: chat input, 3: message
+    #[serde(rename = "type")]
+    ty: u8,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    options: Vec<CommandOption>,
+    integration_types: &'static [u8],
+    contexts: &'static [u8],
+}
+
+#[derive(Serialize)]
+struct CommandOption {
+    name: &'static str,
+    description: String,
+    /// 1: subcommand, 2: subcommand group, 3: string, 4: integer
+    #[serde(rename = "type")]
+    ty: u8,
+    /// only for the options which are not subcommands.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    required: Option<bool>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    autocomplete: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    options: Vec<CommandOption>,
+}
+
+impl From<&CommandSpec> for CommandOption {
+    fn from(spec: &CommandSpec) -> Self {
+        let (ty, options) = if spec.subcommands.is_empty() {
+            (1, spec.options.iter().map(From::from).collect())
+        } else {
+            (2, spec.subcommands.iter().map(From::from).collect())
+        };
+
+        Self {
+            name: spec.name,
+            description: spec.description.to_string(),
+            ty,
+            required: None,
+            autocomplete: false,
+            options,
+        }
+    }
+}
+
+impl From<&OptionSpec> for CommandOption {
+    fn from(spec: &OptionSpec) -> Self {
+        // the values out of the limit are clamped, not rejected. so tell the limit in the description.
+        let description = match spec.limit {
+            Some(ref l) => format!(
+                "{} (default: {}, max: {})",
+                spec.description, l.default, l.max
+            ),
+            None => spec.description.to_string(),
+        };
+
+        let ty = match spec.ty {
+            OptionType::String => 3,
+            OptionType::Integer => 4,
+        };
+
+        Self {
+            name: spec.name,
+            description,
+            ty,
+            required: Some(spec.required),
+            autocomplete: spec.autocomplete,
+            options: vec![],
+        }
+    }
+}
+
+fn commands() -> [ApplicationCommand; 2] {
+    [
+        ApplicationCommand {
+            name: SLASH_COMMAND_NAME,
+            description: Some(SLASH_COMMAND_DESCRIPTION),
+            ty: 1,
+            options: COMMANDS.iter().map(From::from).collect(),
+            integration_types: INTEGRATION_TYPES,
+            contexts: CONTEXTS,
+        },
+        ApplicationCommand {
+            name: SAVE_MESSAGE_COMMAND_NAME,
+            description: None,
+            ty: 3,
+            options: vec![],
+            integration_types: INTEGRATION_TYPES,
+            contexts: CONTEXTS,
+        },
+    ]
+}
+
+/// the commands to be registered to discord, one value for each.
+pub fn application_commands() -> Result<Vec<Value>> {
+    commands()
+        .iter()
+        .map(|x| serde_json::to_value(x).context("failed to serialize command"))
+        .collect()
+}
+
+/// the json to be registered to discord. discord_slash_command.json is generated by this.
+pub fn application_commands_json() -> Result<String> {
+    // the same indent as the file written by hand before.
+    let mut buf = vec![];
+    let mut serializer =
+        Serializer::with_formatter(&mut buf, PrettyFormatter::with_indent(b"    "));
+
+    commands()
+        .serialize(&mut serializer)
+        .context("failed to serialize commands")?;
+
+    let mut json = String::from_utf8(buf).context("serde_json wrote invalid utf-8")?;
+    json.push('\n');
+
+    Ok(json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_file_is_up_to_date() {
+        // regenerate it with `cargo run --features discord_webhook --bin discord_commands`.
+        assert_eq!(
+            application_commands_json().unwrap(),
+            include_str!("../../../discord_slash_command.json"),
+            "discord_slash_command.json is stale"
+        );
+    }
+}
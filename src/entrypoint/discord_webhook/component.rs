@@ -0,0 +1,177 @@
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use warp::{
+    reject::{custom as custom_reject, Rejection},
+    reply::Json,
+};
+
+use super::{
+    interaction::{get_actor, respond, run, try_parse, RunCommandError},
+    model::ComponentRequest,
+    reply::{Reply, EPHEMERAL},
+    BadRequest,
+};
+use crate::{
+    command::{Command, CommandOutput, SearchBy},
+    db::MeigenDatabase,
+    permission::Permissions,
+    Synced,
+};
+
+// discord rejects custom_id longer than this.
+const CUSTOM_ID_LENGTH_LIMIT: usize = 100;
+
+/// the page buttons remember the command in their custom_id, as `{button}:{command}:{count}:{page}:{argument}`.
+pub(super) struct PageButtons {
+    name: &'static str,
+    count: Option<u8>,
+    argument: String,
+}
+
+impl PageButtons {
+    /// None if the command can't move between pages with the buttons.
+    pub(super) fn new(command: &Command) -> Option<Self> {
+        let (name, count, argument) = match command {
+            Command::List { count, .. } => ("list", *count, ""),
+            Command::Search { by, count, .. } => match by {
+                SearchBy::Author(x) => ("author", *count, x.as_str()),
+                SearchBy::Content(x) => ("content", *count, x.as_str()),
+                SearchBy::Query(x) => ("query", *count, x.as_str()),
+            },
+            _ => return None,
+        };
+
+        Some(Self {
+            name,
+            count,
+            argument: argument.to_string(),
+        })
+    }
+
+    fn parse(custom_id: &str) -> Option<Command> {
+        // the argument comes last, so that it can contain colons.
+        let mut parts = custom_id.splitn(5, ':');
+
+        let _button = parts.next()?;
+        let name = parts.next()?;
+
+        let count = match parts.next()? {
+            "" => None,
+            x => Some(x.parse().ok()?),
+        };
+
+        let page = Some(parts.next()?.parse().ok()?);
+        let argument = parts.next()?.to_string();
+
+        let by = match name {
+            "list" => return Some(Command::List { count, page }),
+            "author" => SearchBy::Author(argument),
+            "content" => SearchBy::Content(argument),
+            "query" => SearchBy::Query(argument),
+            _ => return None,
+        };
+
+        Some(Command::Search { by, count, page })
+    }
+
+    pub(super) fn render(&self, output: &CommandOutput) -> Vec<Value> {
+        let (page, last) = (output.page, output.total_pages);
+
+        // there is no other page to move to.
+        if last == 0 || (last == 1 && page == 1) {
+            return vec![];
+        }
+
+        let count = self.count.map(|x| x.to_string()).unwrap_or_default();
+
+        // custom_id must be unique in the message, so the button name is the part of it.
+        let buttons = [
+            ("first", "≪", 1, page > 1),
+            ("prev", "<", (page - 1).min(last), page > 1),
+            ("next", ">", page + 1, page < last),
+            ("last", "≫", last, page != last),
+        ];
+
+        let mut components = Vec::with_capacity(buttons.len());
+
+        for (button, label, target, enabled) in buttons {
+            let custom_id = format!(
+                "{}:{}:{}:{}:{}",
+                button, self.name, count, target, self.argument
+            );
+
+            // the search words were too long to remember. the user can still use the page option.
+            if custom_id.chars().count() > CUSTOM_ID_LENGTH_LIMIT {
+                return vec![];
+            }
+
+            components.push(json!({
+                "type": 2,
+                // secondary (grey)
+                "style": 2,
+                "label": label,
+                "custom_id": custom_id,
+                "disabled": !enabled,
+            }));
+        }
+
+        vec![json!({
+            // action row
+            "type": 1,
+            "components": components,
+        })]
+    }
+}
+
+pub(super) async fn on_component(
+    body: String,
+    db: Synced<impl MeigenDatabase>,
+    permissions: Arc<Permissions>,
+) -> Result<Json, Rejection> {
+    let request = try_parse::<ComponentRequest>(&body)?;
+
+    let command = PageButtons::parse(&request.data.custom_id).ok_or_else(|| {
+        tracing::info!("unknown custom_id: {}", request.data.custom_id);
+        custom_reject(BadRequest)
+    })?;
+
+    let cmd_result = match get_actor(&request.invoker) {
+        Ok(actor) => run(command, db, &permissions, &actor).await,
+        Err(e) => Err(e),
+    };
+
+    respond(response_type(&cmd_result), cmd_result, &permissions)
+}
+
+fn response_type(cmd_result: &Result<Reply, RunCommandError>) -> u8 {
+    match cmd_result {
+        // UpdateMessage: edit the message which the button is attached to
+        Ok(reply) if reply.flags & EPHEMERAL == 0 => 7,
+
+        // ChannelMessageWithSource: discord ignores the flags when editing the message,
+        // so errors are sent as a new message. the page and its buttons stay as they are.
+        _ => 4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::CommandError;
+
+    #[test]
+    fn only_pages_replace_the_message() {
+        let page = Ok(Reply::from(CommandOutput::message("page 2")));
+        assert_eq!(response_type(&page), 7);
+
+        let error = Ok(Reply::from(CommandOutput::error(
+            CommandError::NotFound,
+            "no such page",
+        )));
+        assert_eq!(response_type(&error), 4);
+
+        let failed = Err(RunCommandError::InternalServerError(anyhow::anyhow!("db")));
+        assert_eq!(response_type(&failed), 4);
+    }
+}
@@ -0,0 +1,112 @@
+use std::time::Duration;
+
+use anyhow::{Context as _, Result};
+use reqwest::{header::CONTENT_TYPE, Method, RequestBuilder};
+use serde_json::Value;
+
+/// sends the result of a deferred interaction via the interaction webhook.
+/// see https://discord.com/developers/docs/interactions/receiving-and-responding#followup-messages
+pub(super) struct FollowUp {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl FollowUp {
+    pub(super) fn new(base_url: String) -> Result<Self> {
+        let client = reqwest::ClientBuilder::new()
+            .connect_timeout(Duration::from_secs(5))
+            .timeout(Duration::from_secs(10))
+            .build()
+            .context("failed to build http client")?;
+
+        Ok(Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        })
+    }
+
+    /// replaces the "thinking..." message shown by the deferred response.
+    /// the message stays visible to everyone even if `data` has the ephemeral flag,
+    /// as the visibility is decided when deferring.
+    pub(super) async fn edit_original(
+        &self,
+        application_id: &str,
+        token: &str,
+        data: &Value,
+    ) -> Result<()> {
+        let url = self.original_url(application_id, token);
+        self.send(self.json(Method::PATCH, url, data)).await
+    }
+
+    fn original_url(&self, application_id: &str, token: &str) -> String {
+        format!(
+            "{}/webhooks/{}/{}/messages/@original",
+            self.base_url, application_id, token
+        )
+    }
+
+    fn json(&self, method: Method, url: String, data: &Value) -> RequestBuilder {
+        self.client
+            .request(method, url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(data.to_string())
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<()> {
+        let response = request.send().await.context("failed to send request")?;
+        let status = response.status();
+
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("discord returned {}: {}", status, body);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use warp::http::StatusCode;
+
+    use super::*;
+    use crate::entrypoint::discord_webhook::mock_server::MockServer;
+
+    #[tokio::test]
+    async fn edit_original_patches_the_original_message() {
+        let server = MockServer::start(|_| (StatusCode::OK, "{}".into()));
+        let followup = FollowUp::new(format!("{}/", server.url)).unwrap();
+
+        followup
+            .edit_original("123", "tok", &json!({ "content": "done" }))
+            .await
+            .unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "PATCH");
+        assert_eq!(requests[0].path, "/webhooks/123/tok/messages/@original");
+        assert_eq!(
+            requests[0].content_type.as_deref(),
+            Some("application/json")
+        );
+        assert_eq!(
+            serde_json::from_str::<Value>(&requests[0].body).unwrap(),
+            json!({ "content": "done" })
+        );
+    }
+
+    #[tokio::test]
+    async fn edit_original_fails_on_error_status() {
+        let server = MockServer::start(|_| (StatusCode::NOT_FOUND, "unknown webhook".into()));
+        let followup = FollowUp::new(server.url.clone()).unwrap();
+
+        let error = followup
+            .edit_original("123", "tok", &json!({ "content": "done" }))
+            .await
+            .unwrap_err();
+
+        assert!(format!("{:#}", error).contains("unknown webhook"));
+    }
+}
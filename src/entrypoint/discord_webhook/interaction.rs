@@ -1,243 +1,392 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
 use serde::de::DeserializeOwned;
-use serde_json::json;
+use serde_json::{json, Value};
 use warp::{
     reject::{custom as custom_reject, Rejection},
     reply::{json as reply_json, Json},
 };
 
 use crate::{
+    command::{
+        registry::{self, ParseError, Syntax},
+        Command, CommandError, CommandOutput,
+    },
     db::MeigenDatabase,
-    entrypoint::discord_webhook::{model::*, JsonDeserializeError},
+    entrypoint::discord_webhook::{
+        component::PageButtons, followup::FollowUp, modal, model::*, reply::Reply,
+        JsonDeserializeError,
+    },
+    model::MeigenSource,
+    permission::{Actor, Permissions},
     Synced,
 };
 
-fn try_parse<T: DeserializeOwned>(data: &str) -> Result<T, Rejection> {
+pub(super) fn try_parse<T: DeserializeOwned>(data: &str) -> Result<T, Rejection> {
     serde_json::from_str(data).map_err(|e| {
         tracing::info!("failed to parse json: {:?}", e);
         custom_reject(JsonDeserializeError)
     })
 }
 
+// discord shows "interaction failed" if it gets no response in 3 seconds.
+const DEFER_AFTER: Duration = Duration::from_secs(2);
+
 pub(super) async fn on_interaction(
     body: String,
     db: Synced<impl MeigenDatabase>,
+    permissions: Arc<Permissions>,
+    followup: Arc<FollowUp>,
 ) -> Result<Json, Rejection> {
     let request = try_parse::<Request>(&body)?;
 
-    let cmd_result = run_command(db, &request).await;
+    // modals must be the first response, so they can't be deferred.
+    if modal::is_requested(&request) {
+        return Ok(reply_json(&modal::make_modal()));
+    }
+
+    let application_id = request.application_id.clone();
+    let token = request.token.clone();
+
+    let task = {
+        let permissions = Arc::clone(&permissions);
+        async move { run_command(db, &permissions, &request).await }
+    };
+
+    respond_in_time(task, application_id, token, permissions, followup).await
+}
+
+/// responds with the result if the task finishes soon. otherwise defers and sends it as a follow-up message.
+pub(super) async fn respond_in_time(
+    task: impl Future<Output = Result<Reply, RunCommandError>> + Send + 'static,
+    application_id: String,
+    token: String,
+    permissions: Arc<Permissions>,
+    followup: Arc<FollowUp>,
+) -> Result<Json, Rejection> {
+    let mut task = tokio::spawn(task);
+
+    // the task keeps running even if it timed out, as it is polled by reference.
+    if let Ok(joined) = tokio::time::timeout(DEFER_AFTER, &mut task).await {
+        let cmd_result =
+            joined.unwrap_or_else(|e| Err(RunCommandError::InternalServerError(e.into())));
+
+        // ChannelMessageWithSource: respond with a message, showing the user's input
+        return respond(4, cmd_result, &permissions);
+    }
+
+    tokio::spawn(async move {
+        let cmd_result = task
+            .await
+            .unwrap_or_else(|e| Err(RunCommandError::InternalServerError(e.into())));
+
+        // the deferred response was already sent. there is no way to reject the request now.
+        let data = message_data(cmd_result, &permissions)
+            .unwrap_or_else(|| json!({ "content": "リクエストが正しくありませんでした。" }));
+
+        // errors are shown to everyone too. a follow-up can't hide the deferred message,
+        // and deleting it and sending another one makes discord drop the error instead.
+        if let Err(e) = followup.edit_original(&application_id, &token, &data).await {
+            tracing::error!("failed to send the follow-up message: {:?}", e);
+        }
+    });
+
+    // DeferredChannelMessageWithSource: show "thinking..." until the follow-up message is sent
+    Ok(reply_json(&json!({ "type": 5 })))
+}
+
+pub(super) fn respond(
+    response_type: u8,
+    cmd_result: Result<Reply, RunCommandError>,
+    permissions: &Permissions,
+) -> Result<Json, Rejection> {
+    let data =
+        message_data(cmd_result, permissions).ok_or_else(|| custom_reject(super::BadRequest))?;
+
+    Ok(reply_json(&json!({
+        "type": response_type,
+        "data": data,
+    })))
+}
 
-    let msg = match cmd_result {
-        Ok(v) => v,
+/// builds the message object to be sent. returns None if the request was invalid.
+fn message_data(
+    cmd_result: Result<Reply, RunCommandError>,
+    permissions: &Permissions,
+) -> Option<Value> {
+    // meigens can contain mentions typed by users. don't ping them.
+    let (reply, allowed_mentions) = match cmd_result {
+        Ok(v) => (v, json!({ "parse": [] })),
         Err(e) => {
             tracing::error!("{:?}", e);
             match e {
-                RunCommandError::InvalidRequest(_) => return Err(custom_reject(super::BadRequest)),
+                RunCommandError::InvalidRequest(_) => return None,
 
                 RunCommandError::InternalServerError(e) => {
                     tracing::error!("something went wrong: {:?}", e);
-                    String::from(
-                        "処理がうまくいきませんでした。 <@391857452360007680> ログを見てください。",
-                    )
+
+                    let admins = permissions
+                        .admin_user_ids()
+                        .map(|x| format!("<@{}> ", x))
+                        .collect::<String>();
+
+                    let msg = format!(
+                        "処理がうまくいきませんでした。 {}ログを見てください。",
+                        admins
+                    );
+
+                    // not ephemeral, so that the admins are notified.
+                    (Reply::from(msg), json!({ "parse": ["users"] }))
                 }
             }
         }
     };
 
-    Ok(reply_json(&json!({
-        // ChannelMessageWithSource: respond with a message, showing the user's input
-        "type": 4,
-        "data": {
-            "content": msg
-        }
-    })))
+    Some(json!({
+        "content": reply.content,
+        "embeds": reply.embeds,
+        "components": reply.components,
+        "allowed_mentions": allowed_mentions,
+        "flags": reply.flags,
+    }))
 }
 
 #[derive(Debug)]
-enum RunCommandError {
+pub(super) enum RunCommandError {
     InternalServerError(anyhow::Error),
     InvalidRequest(&'static str),
 }
 
 async fn run_command(
     db: Synced<impl MeigenDatabase>,
+    permissions: &Permissions,
     req: &Request,
-) -> Result<String, RunCommandError> {
+) -> Result<Reply, RunCommandError> {
     use RunCommandError::*;
 
-    use crate::command::*;
-
-    let first_opt = req
-        .data
-        .options
-        .first()
-        .ok_or(InvalidRequest("meigen command requires subcommand"))?;
-
-    fn get<'a>(opt: &'a RequestOption, key: &str) -> Option<&'a String> {
-        opt.options
-            .as_ref()?
-            .iter()
-            .find(|x| x.name == key)?
-            .value
-            .as_ref()
+    // "Save as meigen" is the only message context menu command.
+    if req.data.ty == 3 {
+        return save_message(db, permissions, req).await;
     }
 
-    #[allow(clippy::unnecessary_wraps)]
-    fn on_parse_fail(
-        field_name: &'static str,
-        ty: &'static str,
-    ) -> Result<String, RunCommandError> {
-        Ok(format!("{}フィールド({})のパースに失敗しました。もしかしたら数字が大きすぎるとか小さすぎるとかマイナスだからとかかもしれません。", field_name, ty))
-    }
+    // walk down the subcommand groups, e.g. `search` -> `author`.
+    let mut path = vec![];
+    let mut current = req.data.options.first();
 
-    macro_rules! extract {
-        ({
-            from: $from:ident
-            $(,required: [$($required_field:ident$(:$required_field_type:ty)?),+$(,)?])?
-            $(,optional: [$($optional_field:ident$(:$optional_field_type:ty)?),+$(,)?])?
-            $(,)?
-        }) => {{
-            $($(let $required_field = match get($from, stringify!($required_field)) {
-                Some(value) => {
-                    $(let value = match value.parse::<$required_field_type>() {
-                        Ok(v) => v,
-                        Err(_) => return on_parse_fail(stringify!($required_field), stringify!($required_field_type))
-                    };)?
-                    value
-                },
-                None => {
-                    return Err(InvalidRequest(
-                        concat!(stringify!($required_field), " field is missing")
-                    ))
-                }
-            };)+)?
-            $($(let $optional_field = match get($from, stringify!($optional_field)) {
-                Some(value) => {
-                    $(let value = match value.parse::<$optional_field_type>() {
-                        Ok(v) => v,
-                        Err(_) => return on_parse_fail(stringify!($optional_field), stringify!($optional_field_type))
-                    };)?
-                    Some(value)
-                },
-                None => None,
-            };)+)?
-
-            (($($($required_field),+)?),($($($optional_field),+)?))
-        }};
-    }
+    let leaf = loop {
+        let option = current.ok_or(InvalidRequest("meigen command requires subcommand"))?;
+        path.push(option.name.as_str());
 
-    match first_opt.name.as_str() {
-        "make" => {
-            let ((author, content), ()) = extract!({
-                from: first_opt,
-                required: [author, content]
-            });
+        let (spec, _) = registry::find(&path).ok_or(InvalidRequest("unexpected subcommand"))?;
 
-            make(db, author, content).await
+        if spec.subcommands.is_empty() {
+            break option;
         }
 
-        "search" => {
-            let sub = first_opt.options.as_ref().and_then(|x| x.first()).ok_or(
-                RunCommandError::InvalidRequest("search command requires subcommand"),
-            )?;
+        current = option.options.as_ref().and_then(|x| x.first());
+    };
 
-            match sub.name.as_str() {
-                "author" => {
-                    let (author, (show_count, page)) = extract!({
-                        from: sub,
-                        required: [author],
-                        optional: [count: u8, page: u32]
-                    });
+    let values = leaf.options.as_deref().unwrap_or_default();
+    let get = |name: &str| values.iter().find(|x| x.name == name)?.value.as_deref();
 
-                    search_author(db, author, show_count, page).await
-                }
+    let command = match Command::parse(&path, Syntax::Slash, get) {
+        Ok(c) => c,
+        Err(ParseError::Invalid(msg)) => {
+            return Ok(Reply::from(CommandOutput::error(
+                CommandError::InvalidArgument,
+                msg,
+            )))
+        }
+        Err(ParseError::Missing(name)) => {
+            tracing::info!("{} field is missing", name);
+            return Err(InvalidRequest("required field is missing"));
+        }
+        Err(ParseError::UnknownCommand) => return Err(InvalidRequest("unexpected subcommand")),
+    };
 
-                "content" => {
-                    let (content, (show_count, page)) = extract!({
-                        from: sub,
-                        required: [content],
-                        optional: [count: u8, page: u32]
-                    });
+    let actor = get_actor(&req.invoker)?;
 
-                    search_content(db, content, show_count, page).await
-                }
-                _ => return Err(InvalidRequest("unexpected subcommand for search command")),
-            }
-        }
-        "love" => {
-            let (req_id, ()) = extract!({
-                from: first_opt,
-                required: [id: u32],
-            });
+    run(command, db, permissions, &actor).await
+}
 
-            let user_id = get_requesting_user_id(req)?;
+/// runs the command, and attaches the page buttons if the command has pages.
+pub(super) async fn run(
+    command: Command,
+    db: Synced<impl MeigenDatabase>,
+    permissions: &Permissions,
+    actor: &Actor,
+) -> Result<Reply, RunCommandError> {
+    let buttons = PageButtons::new(&command);
+
+    let output = command
+        .run(db, permissions, actor)
+        .await
+        .map_err(RunCommandError::InternalServerError)?;
+
+    let components = match buttons {
+        Some(b) => b.render(&output),
+        None => vec![],
+    };
 
-            love(db, req_id, user_id).await
-        }
-        "unlove" => {
-            let (req_id, ()) = extract!({
-                from: first_opt,
-                required: [id: u32],
-            });
+    Ok(Reply {
+        components,
+        ..Reply::from(output)
+    })
+}
 
-            let user_id = get_requesting_user_id(req)?;
+async fn save_message(
+    db: Synced<impl MeigenDatabase>,
+    permissions: &Permissions,
+    req: &Request,
+) -> Result<Reply, RunCommandError> {
+    use RunCommandError::*;
 
-            unlove(db, req_id, user_id).await
-        }
-        "help" => help().await,
-        "id" => {
-            let (req_id, ()) = extract!({
-                from: first_opt,
-                required: [id: u32],
-            });
-
-            id(db, req_id).await
-        }
-        "gophersay" => {
-            let (req_id, ()) = extract!({
-                from: first_opt,
-                required: [id: u32],
-            });
+    let message = req
+        .data
+        .target_id
+        .as_ref()
+        .and_then(|id| req.data.resolved.as_ref()?.messages.get(id))
+        .ok_or(InvalidRequest("target message is missing"))?;
+
+    if message.content.trim().is_empty() {
+        return Ok(Reply::from(CommandOutput::error(
+            CommandError::InvalidArgument,
+            "本文のないメッセージは名言にできません。",
+        )));
+    }
 
-            gophersay(db, req_id).await
-        }
-        "list" => {
-            let ((), (count, page)) = extract!({
-                from: first_opt,
-                optional: [count: u8, page: u32],
-            });
+    let parse_id = |id: &str| {
+        id.parse::<u64>().map_err(|e| {
+            tracing::info!("failed to deserialize snowflake: {}", e);
+            InvalidRequest("snowflake was invalid")
+        })
+    };
 
-            list(db, count, page).await
-        }
-        "random" => {
-            let ((), count) = extract!({
-                from: first_opt,
-                optional: [count: u8],
-            });
+    let source = MeigenSource {
+        guild_id: req.guild_id.as_deref().map(parse_id).transpose()?,
+        channel_id: parse_id(&message.channel_id)?,
+        message_id: parse_id(&message.id)?,
+    };
 
-            random(db, count).await
-        }
-        "status" => status(db).await,
-        "delete" => {
-            let (meigenid, ()) = extract!({
-                from: first_opt,
-                required: [id: u32],
-            });
+    let command = Command::Make {
+        author: message.author.display_name().to_string(),
+        content: message.content.clone(),
+        source: Some(source),
+    };
 
-            let id = get_requesting_user_id(req)?;
+    let actor = get_actor(&req.invoker)?;
 
-            delete(db, meigenid, id).await
-        }
-        _ => return Err(InvalidRequest("unexpected subcommand")),
-    }
-    .map_err(InternalServerError)
+    run(command, db, permissions, &actor).await
 }
 
-fn get_requesting_user_id(req: &Request) -> Result<u64, RunCommandError> {
-    match req.member.user.id.parse::<u64>() {
+fn get_requesting_user_id(invoker: &Invoker) -> Result<u64, RunCommandError> {
+    let user = invoker.user().ok_or(RunCommandError::InvalidRequest(
+        "neither member nor user is given",
+    ))?;
+
+    match user.id.parse::<u64>() {
         Ok(v) => Ok(v),
         Err(e) => {
-            tracing::info!("failed to deserialize request.member.user.id: {}", e);
+            tracing::info!("failed to deserialize user id: {}", e);
             Err(RunCommandError::InvalidRequest("user id was invalid"))
         }
     }
 }
+
+pub(super) fn get_actor(invoker: &Invoker) -> Result<Actor, RunCommandError> {
+    // roles are only in guilds. in DMs, only the permissions given to the user id apply.
+    let role_ids = invoker
+        .role_ids()
+        .iter()
+        .map(|x| x.parse::<u64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| {
+            tracing::info!("failed to deserialize request.member.roles: {}", e);
+            RunCommandError::InvalidRequest("role id was invalid")
+        })?;
+
+    Ok(Actor {
+        user_id: get_requesting_user_id(invoker)?,
+        role_ids,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use warp::{http::StatusCode, Reply as _};
+
+    use super::*;
+    use crate::entrypoint::discord_webhook::mock_server::MockServer;
+
+    async fn body_of(json: Json) -> Value {
+        let bytes = warp::hyper::body::to_bytes(json.into_response().into_body())
+            .await
+            .unwrap();
+
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    async fn respond_with(output: CommandOutput, delay: Duration) -> (Value, MockServer) {
+        let server = MockServer::start(|_| (StatusCode::OK, "{}".into()));
+        let followup = Arc::new(FollowUp::new(server.url.clone()).unwrap());
+
+        let task = async move {
+            tokio::time::sleep(delay).await;
+            Ok(Reply::from(output))
+        };
+
+        let response = respond_in_time(
+            task,
+            "123".into(),
+            "tok".into(),
+            Arc::new(Permissions::console()),
+            followup,
+        )
+        .await
+        .unwrap();
+
+        (body_of(response).await, server)
+    }
+
+    #[tokio::test]
+    async fn fast_result_is_the_response() {
+        let (response, server) = respond_with(CommandOutput::message("done"), Duration::ZERO).await;
+
+        assert_eq!(response["type"], 4);
+        assert_eq!(response["data"]["content"], "done");
+        assert!(server.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn slow_result_is_sent_after_deferring() {
+        let delay = DEFER_AFTER + Duration::from_millis(300);
+        let (response, server) = respond_with(CommandOutput::message("done"), delay).await;
+
+        assert_eq!(response, json!({ "type": 5 }));
+
+        let requests = server.wait_for(1).await;
+        assert_eq!(requests[0].method, "PATCH");
+        assert_eq!(requests[0].path, "/webhooks/123/tok/messages/@original");
+
+        let data = serde_json::from_str::<Value>(&requests[0].body).unwrap();
+        assert_eq!(data["content"], "done");
+        assert_eq!(data["flags"], 0);
+    }
+
+    #[tokio::test]
+    async fn slow_error_replaces_the_deferred_message() {
+        let delay = DEFER_AFTER + Duration::from_millis(300);
+        let output = CommandOutput::error(CommandError::NotFound, "missing");
+        let (response, server) = respond_with(output, delay).await;
+
+        assert_eq!(response, json!({ "type": 5 }));
+
+        let requests = server.wait_for(1).await;
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "PATCH");
+        assert_eq!(requests[0].path, "/webhooks/123/tok/messages/@original");
+
+        let data = serde_json::from_str::<Value>(&requests[0].body).unwrap();
+        assert_eq!(data["content"], "missing");
+    }
+}
@@ -0,0 +1,83 @@
+//! a local http server which stands for the discord api in tests.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use warp::{http::StatusCode, hyper::body::Bytes, path::FullPath, Filter};
+
+#[derive(Debug, Clone)]
+pub(super) struct Recorded {
+    pub(super) method: String,
+    pub(super) path: String,
+    pub(super) authorization: Option<String>,
+    pub(super) content_type: Option<String>,
+    pub(super) body: String,
+}
+
+pub(super) struct MockServer {
+    pub(super) url: String,
+    requests: Arc<Mutex<Vec<Recorded>>>,
+}
+
+impl MockServer {
+    /// records every request, and responds with what `respond` returns.
+    pub(super) fn start<F>(respond: F) -> Self
+    where
+        F: Fn(&Recorded) -> (StatusCode, String) + Clone + Send + Sync + 'static,
+    {
+        let requests = Arc::new(Mutex::new(vec![]));
+
+        let route = {
+            let requests = Arc::clone(&requests);
+
+            warp::method()
+                .and(warp::path::full())
+                .and(warp::header::optional::<String>("authorization"))
+                .and(warp::header::optional::<String>("content-type"))
+                .and(warp::body::bytes())
+                .map(
+                    move |method, path: FullPath, authorization, content_type, body: Bytes| {
+                        let request = Recorded {
+                            method: format!("{}", method),
+                            path: path.as_str().to_string(),
+                            authorization,
+                            content_type,
+                            body: String::from_utf8_lossy(&body).into_owned(),
+                        };
+
+                        let (status, body) = respond(&request);
+                        requests.lock().unwrap().push(request);
+
+                        warp::reply::with_status(body, status)
+                    },
+                )
+        };
+
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        Self {
+            url: format!("http://{}", addr),
+            requests,
+        }
+    }
+
+    pub(super) fn requests(&self) -> Vec<Recorded> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// waits for the requests sent in background.
+    pub(super) async fn wait_for(&self, count: usize) -> Vec<Recorded> {
+        for _ in 0..100 {
+            if self.requests.lock().unwrap().len() >= count {
+                return self.requests();
+            }
+
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        panic!("expected {} requests, got {:?}", count, self.requests());
+    }
+}
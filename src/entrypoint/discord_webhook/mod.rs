@@ -1,13 +1,27 @@
+mod autocomplete;
+pub mod commands;
+mod component;
+mod followup;
 mod interaction;
+#[cfg(test)]
+mod mock_server;
+mod modal;
 mod model;
+pub mod register;
+mod reply;
 mod verify;
 
-use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+use std::{convert::Infallible, net::SocketAddr, sync::Arc, time::Duration};
 
 use anyhow::{Context, Result};
+use autocomplete::on_autocomplete;
+use component::on_component;
+use followup::FollowUp;
 use interaction::on_interaction;
+use modal::on_modal_submit;
 use serde_json::json;
 use tokio::sync::RwLock;
+use verify::Verifier;
 use warp::{
     http::StatusCode,
     reject::Reject,
@@ -15,15 +29,24 @@ use warp::{
     Filter, Rejection, Reply,
 };
 
-use crate::{db::MeigenDatabase, Synced};
+use crate::{db::MeigenDatabase, permission::Permissions, Synced};
 
 // 512KB limit
 const CONTENT_LENGTH_LIMIT: u64 = 1024 * 512;
 
+pub const DEFAULT_DISCORD_API_BASE_URL: &str = "https://discord.com/api/v10";
+
+pub const DEFAULT_SIGNATURE_MAX_AGE: Duration = Duration::from_secs(60);
+
 // TODO: builder pattern is more rust-ish
 pub struct DiscordWebhookServerOptions<D: MeigenDatabase> {
     pub app_public_key: String,
     pub db: D,
+    pub permissions: Permissions,
+    /// where the follow-up messages are sent. usually DEFAULT_DISCORD_API_BASE_URL.
+    pub discord_api_base_url: String,
+    /// requests signed longer ago than this are rejected, so that captured ones can't be replayed later.
+    pub signature_max_age: Duration,
 }
 
 impl<D: MeigenDatabase> DiscordWebhookServerOptions<D> {
@@ -32,23 +55,32 @@ impl<D: MeigenDatabase> DiscordWebhookServerOptions<D> {
             .context("Failed to parse app_public_key into bytes")?;
 
         Ok(DiscordWebhookServer {
-            app_public_key_bytes: bytes,
+            verifier: Verifier::new(bytes, self.signature_max_age),
             db: Arc::new(RwLock::new(self.db)),
+            permissions: Arc::new(self.permissions),
+            followup: Arc::new(
+                FollowUp::new(self.discord_api_base_url)
+                    .context("Failed to create follow-up client")?,
+            ),
         })
     }
 }
 
 pub struct DiscordWebhookServer<D: MeigenDatabase> {
-    app_public_key_bytes: Vec<u8>,
+    verifier: Verifier,
     db: Synced<D>,
+    permissions: Arc<Permissions>,
+    followup: Arc<FollowUp>,
 }
 
 impl<D: MeigenDatabase> DiscordWebhookServer<D> {
     pub async fn start(self, ip: impl Into<SocketAddr>) -> Result<()> {
         let route = warp::post()
             .and(warp::body::content_length_limit(CONTENT_LENGTH_LIMIT))
-            .and(verify::filter(self.app_public_key_bytes))
+            .and(verify::filter(self.verifier))
             .and(inject(self.db))
+            .and(inject(self.permissions))
+            .and(inject(self.followup))
             .and_then(on_request)
             .recover(recover)
             .with(warp::trace::request());
@@ -80,7 +112,12 @@ warp_rejects! {
     BadRequest,
 }
 
-async fn on_request(body: String, db: Synced<impl MeigenDatabase>) -> Result<Json, Rejection> {
+async fn on_request(
+    body: String,
+    db: Synced<impl MeigenDatabase>,
+    permissions: Arc<Permissions>,
+    followup: Arc<FollowUp>,
+) -> Result<Json, Rejection> {
     #[derive(serde::Deserialize)]
     struct DiscordRequest {
         #[serde(rename = "type")]
@@ -98,7 +135,16 @@ async fn on_request(body: String, db: Synced<impl MeigenDatabase>) -> Result<Jso
         }
 
         // interaction
-        2 => on_interaction(body, db).await,
+        2 => on_interaction(body, db, permissions, followup).await,
+
+        // message component, e.g. button
+        3 => on_component(body, db, permissions).await,
+
+        // the user is typing an option which has "autocomplete": true
+        4 => on_autocomplete(body, db).await,
+
+        // modal submit
+        5 => on_modal_submit(body, db, permissions, followup).await,
 
         // ???
         _ => Err(warp::reject::custom(UnknownEventType)),
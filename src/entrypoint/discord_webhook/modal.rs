@@ -0,0 +1,117 @@
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use warp::{
+    reject::{custom as custom_reject, Rejection},
+    reply::Json,
+};
+
+use super::{
+    followup::FollowUp,
+    interaction::{get_actor, respond_in_time, run, try_parse, RunCommandError},
+    model::{ModalSubmitRequest, Request},
+    BadRequest,
+};
+use crate::{
+    command::{Command, MEIGEN_LENGTH_LIMIT},
+    db::MeigenDatabase,
+    permission::Permissions,
+    Synced,
+};
+
+const MAKE_MODAL_ID: &str = "make";
+
+/// true if the user ran `/gmeigen make` without any options.
+pub(super) fn is_requested(request: &Request) -> bool {
+    match request.data.options.first() {
+        Some(x) if request.data.ty == 1 && x.name == "make" => match x.options {
+            Some(ref options) => options.is_empty(),
+            None => true,
+        },
+        _ => false,
+    }
+}
+
+/// text inputs can contain newlines, while slash command options can't.
+pub(super) fn make_modal() -> Value {
+    json!({
+        // Modal
+        "type": 9,
+        "data": {
+            "custom_id": MAKE_MODAL_ID,
+            "title": "名言を登録",
+            "components": [
+                {
+                    // action row
+                    "type": 1,
+                    "components": [{
+                        // text input
+                        "type": 4,
+                        "custom_id": "author",
+                        "label": "作者",
+                        // short
+                        "style": 1,
+                        "required": true,
+                        "max_length": MEIGEN_LENGTH_LIMIT,
+                    }],
+                },
+                {
+                    "type": 1,
+                    "components": [{
+                        "type": 4,
+                        "custom_id": "content",
+                        "label": "名言",
+                        // paragraph
+                        "style": 2,
+                        "required": true,
+                        "max_length": MEIGEN_LENGTH_LIMIT,
+                    }],
+                },
+            ],
+        }
+    })
+}
+
+pub(super) async fn on_modal_submit(
+    body: String,
+    db: Synced<impl MeigenDatabase>,
+    permissions: Arc<Permissions>,
+    followup: Arc<FollowUp>,
+) -> Result<Json, Rejection> {
+    let request = try_parse::<ModalSubmitRequest>(&body)?;
+
+    if request.data.custom_id != MAKE_MODAL_ID {
+        tracing::info!("unknown modal: {}", request.data.custom_id);
+        return Err(custom_reject(BadRequest));
+    }
+
+    let application_id = request.application_id.clone();
+    let token = request.token.clone();
+
+    let task = {
+        let permissions = Arc::clone(&permissions);
+
+        async move {
+            let author = request
+                .value("author")
+                .ok_or(RunCommandError::InvalidRequest("author field is missing"))?;
+
+            let content = request
+                .value("content")
+                .ok_or(RunCommandError::InvalidRequest("content field is missing"))?;
+
+            // the length is checked the same way as the slash command.
+            let command = Command::Make {
+                author: author.to_string(),
+                content: content.to_string(),
+                source: None,
+            };
+
+            let actor = get_actor(&request.invoker)?;
+
+            run(command, db, &permissions, &actor).await
+        }
+    };
+
+    respond_in_time(task, application_id, token, permissions, followup).await
+}
@@ -1,4 +1,7 @@
-use std::fmt::{Formatter, Result as FmtResult};
+use std::{
+    collections::HashMap,
+    fmt::{Formatter, Result as FmtResult},
+};
 
 use serde::{
     de::{Deserialize, Deserializer, Error, MapAccess, Visitor},
@@ -7,23 +10,141 @@ use serde::{
 
 #[derive(DeserializeMacro)]
 pub(super) struct Request {
+    pub(super) application_id: String,
+    /// used to send the follow-up messages. valid for 15 minutes.
+    pub(super) token: String,
+    /// None in DMs.
+    pub(super) guild_id: Option<String>,
     pub(super) data: RequestData,
-    pub(super) member: RequestMember,
+    #[serde(flatten)]
+    pub(super) invoker: Invoker,
+}
+
+/// who ran the interaction. discord sends `member` in guilds, and `user` in DMs.
+#[derive(DeserializeMacro)]
+pub(super) struct Invoker {
+    pub(super) member: Option<RequestMember>,
+    pub(super) user: Option<RequestUser>,
+}
+
+impl Invoker {
+    pub(super) fn user(&self) -> Option<&RequestUser> {
+        match self.member {
+            Some(ref member) => Some(&member.user),
+            None => self.user.as_ref(),
+        }
+    }
+
+    /// always empty in DMs.
+    pub(super) fn role_ids(&self) -> &[String] {
+        match self.member {
+            Some(ref member) => &member.roles,
+            None => &[],
+        }
+    }
+}
+
+/// sent when a message component such as a button was clicked.
+#[derive(DeserializeMacro)]
+pub(super) struct ComponentRequest {
+    pub(super) data: ComponentRequestData,
+    #[serde(flatten)]
+    pub(super) invoker: Invoker,
+}
+
+#[derive(DeserializeMacro)]
+pub(super) struct ComponentRequestData {
+    pub(super) custom_id: String,
+}
+
+/// sent when a modal was submitted.
+#[derive(DeserializeMacro)]
+pub(super) struct ModalSubmitRequest {
+    pub(super) application_id: String,
+    pub(super) token: String,
+    pub(super) data: ModalSubmitData,
+    #[serde(flatten)]
+    pub(super) invoker: Invoker,
+}
+
+impl ModalSubmitRequest {
+    /// the value of the text input.
+    pub(super) fn value(&self, custom_id: &str) -> Option<&str> {
+        self.data
+            .components
+            .iter()
+            .flat_map(|x| &x.components)
+            .find(|x| x.custom_id == custom_id)
+            .map(|x| x.value.as_str())
+    }
+}
+
+#[derive(DeserializeMacro)]
+pub(super) struct ModalSubmitData {
+    pub(super) custom_id: String,
+    /// action rows, each of which contains a text input.
+    pub(super) components: Vec<ModalActionRow>,
+}
+
+#[derive(DeserializeMacro)]
+pub(super) struct ModalActionRow {
+    pub(super) components: Vec<ModalTextInput>,
+}
+
+#[derive(DeserializeMacro)]
+pub(super) struct ModalTextInput {
+    pub(super) custom_id: String,
+    #[serde(default)]
+    pub(super) value: String,
 }
 
 #[derive(DeserializeMacro)]
 pub(super) struct RequestMember {
     pub(super) user: RequestUser,
+    #[serde(default)]
+    pub(super) roles: Vec<String>,
 }
 
 #[derive(DeserializeMacro)]
 pub(super) struct RequestUser {
     pub(super) id: String,
+    pub(super) username: String,
+    /// the display name. None if the user has not set it.
+    pub(super) global_name: Option<String>,
+}
+
+impl RequestUser {
+    pub(super) fn display_name(&self) -> &str {
+        self.global_name.as_ref().unwrap_or(&self.username)
+    }
 }
 
 #[derive(DeserializeMacro)]
 pub(super) struct RequestData {
+    /// 1: slash command, 3: message context menu
+    #[serde(rename = "type")]
+    pub(super) ty: u8,
+    // context menu commands have no options.
+    #[serde(default)]
     pub(super) options: Vec<RequestOption>,
+    /// the id of the message which the context menu command was used on.
+    pub(super) target_id: Option<String>,
+    pub(super) resolved: Option<ResolvedData>,
+}
+
+/// the objects referred by the options or the context menu command. each of them is keyed by its id.
+#[derive(DeserializeMacro)]
+pub(super) struct ResolvedData {
+    #[serde(default)]
+    pub(super) messages: HashMap<String, ResolvedMessage>,
+}
+
+#[derive(DeserializeMacro)]
+pub(super) struct ResolvedMessage {
+    pub(super) id: String,
+    pub(super) channel_id: String,
+    pub(super) content: String,
+    pub(super) author: RequestUser,
 }
 
 pub(super) struct RequestOption {
@@ -32,9 +153,11 @@ pub(super) struct RequestOption {
     pub(super) name: String,
     pub(super) value: Option<String>,
     pub(super) options: Option<Vec<RequestOption>>,
+    /// true if the user is typing this option. sent only in autocomplete interactions.
+    pub(super) focused: bool,
 }
 
-const REQUEST_OPTION_FIELDS: &[&str] = &["name", "value", "options"];
+const REQUEST_OPTION_FIELDS: &[&str] = &["name", "value", "options", "focused"];
 
 impl<'d> serde::de::Deserialize<'d> for RequestOption {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
@@ -54,6 +177,7 @@ enum RequestOptionField {
     Value,
     Options,
     Type,
+    Focused,
 }
 
 impl<'d> serde::de::Deserialize<'d> for RequestOptionField {
@@ -71,7 +195,7 @@ impl<'d> Visitor<'d> for RequestOptionFieldVisitor {
     type Value = RequestOptionField;
 
     fn expecting(&self, formatter: &mut Formatter) -> FmtResult {
-        write!(formatter, "type, name, value or options")
+        write!(formatter, "type, name, value, options or focused")
     }
 
     fn visit_str<E>(self, value: &str) -> Result<RequestOptionField, E>
@@ -83,6 +207,7 @@ impl<'d> Visitor<'d> for RequestOptionFieldVisitor {
             "value" => Ok(RequestOptionField::Value),
             "options" => Ok(RequestOptionField::Options),
             "type" => Ok(RequestOptionField::Type),
+            "focused" => Ok(RequestOptionField::Focused),
             _ => Err(Error::unknown_field(value, REQUEST_OPTION_FIELDS)),
         }
     }
@@ -105,6 +230,7 @@ impl<'d> Visitor<'d> for RequestOptionVisitor {
         let mut value: Option<RequestOptionValue> = None;
         let mut options: Option<Vec<RequestOption>> = None;
         let mut ty: Option<i32> = None;
+        let mut focused: Option<bool> = None;
 
         while let Some(key) = map.next_key::<RequestOptionField>()? {
             match key {
@@ -139,6 +265,14 @@ impl<'d> Visitor<'d> for RequestOptionVisitor {
 
                     ty = Some(map.next_value()?);
                 }
+
+                RequestOptionField::Focused => {
+                    if focused.is_some() {
+                        return Err(Error::duplicate_field("focused"));
+                    }
+
+                    focused = Some(map.next_value()?);
+                }
             }
         }
 
@@ -149,6 +283,7 @@ impl<'d> Visitor<'d> for RequestOptionVisitor {
             name,
             value: value.map(|x| x.0),
             options,
+            focused: focused.unwrap_or(false),
         })
     }
 }
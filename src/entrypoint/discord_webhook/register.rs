@@ -0,0 +1,457 @@
+use std::{
+    fmt::{Display, Formatter, Result as FmtResult},
+    time::Duration,
+};
+
+use anyhow::{Context as _, Result};
+use reqwest::{
+    header::{AUTHORIZATION, CONTENT_TYPE},
+    Method, RequestBuilder,
+};
+use serde_json::Value;
+
+/// where the commands are registered.
+pub enum Scope {
+    Global,
+    /// guild commands are updated instantly, so they are useful for testing.
+    Guild(String),
+}
+
+/// one request needed to make the registered commands the same as the local ones.
+pub enum Change {
+    Create(Value),
+    Update { id: String, command: Value },
+    Delete { id: String, name: String },
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Change::Create(command) => write!(f, "create {}", name_of(command)),
+            Change::Update { id, command } => write!(f, "update {} (id: {})", name_of(command), id),
+            Change::Delete { id, name } => write!(f, "delete {} (id: {})", name, id),
+        }
+    }
+}
+
+fn name_of(command: &Value) -> &str {
+    command["name"].as_str().unwrap_or_default()
+}
+
+// commands are identified by the name and the type. 1 (chat input) if the type is omitted.
+fn key_of(command: &Value) -> (&str, u64) {
+    (name_of(command), command["type"].as_u64().unwrap_or(1))
+}
+
+// the fields discord gives to the registered commands. they are not a part of the definition.
+const DISCORD_OWNED_KEYS: &[&str] = &["id", "application_id", "version", "guild_id"];
+
+// whether the field has the same meaning as when it's omitted.
+// discord fills some of the omitted fields, and omits some of the given ones, e.g. `required: false`.
+fn is_default(key: &str, value: &Value) -> bool {
+    match (key, value) {
+        // deprecated by contexts, but still returned.
+        ("dm_permission", Value::Bool(b)) => *b,
+        (_, Value::Null | Value::Bool(false)) => true,
+        (_, Value::String(x)) => x.is_empty(),
+        (_, Value::Array(x)) => x.is_empty(),
+        (_, Value::Object(x)) => x.is_empty(),
+        _ => false,
+    }
+}
+
+/// true if the local command and the registered one mean the same.
+/// the fields on either side are compared, so that the fields removed locally are removed on discord too.
+fn is_same(local: &Value, remote: &Value) -> bool {
+    match (local, remote) {
+        (Value::Object(l), Value::Object(r)) => l
+            .keys()
+            .chain(r.keys())
+            .filter(|k| !DISCORD_OWNED_KEYS.contains(&k.as_str()))
+            .all(|k| match (l.get(k), r.get(k)) {
+                (Some(l), Some(r)) => is_same(l, r) || (is_default(k, l) && is_default(k, r)),
+                (Some(x), None) | (None, Some(x)) => is_default(k, x),
+                (None, None) => true,
+            }),
+        (Value::Array(l), Value::Array(r)) => {
+            l.len() == r.len() && l.iter().zip(r).all(|(l, r)| is_same(l, r))
+        }
+        (l, r) => l == r,
+    }
+}
+
+/// syncs the application commands with the local definitions.
+/// see https://discord.com/developers/docs/interactions/application-commands#registering-a-command
+pub struct CommandRegistrar {
+    client: reqwest::Client,
+    /// `{base_url}/applications/{application_id}/commands`, or the guild one.
+    commands_url: String,
+    bot_token: String,
+    scope: Scope,
+}
+
+impl CommandRegistrar {
+    pub fn new(
+        base_url: &str,
+        application_id: &str,
+        bot_token: String,
+        scope: Scope,
+    ) -> Result<Self> {
+        let client = reqwest::ClientBuilder::new()
+            .connect_timeout(Duration::from_secs(5))
+            .timeout(Duration::from_secs(10))
+            .build()
+            .context("failed to build http client")?;
+
+        let base_url = base_url.trim_end_matches('/');
+
+        let commands_url = match scope {
+            Scope::Global => format!("{}/applications/{}/commands", base_url, application_id),
+            Scope::Guild(ref guild_id) => format!(
+                "{}/applications/{}/guilds/{}/commands",
+                base_url, application_id, guild_id
+            ),
+        };
+
+        Ok(Self {
+            client,
+            commands_url,
+            bot_token,
+            scope,
+        })
+    }
+
+    fn request(&self, method: Method, url: &str) -> RequestBuilder {
+        self.client
+            .request(method, url)
+            .header(AUTHORIZATION, format!("Bot {}", self.bot_token))
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<String> {
+        let response = request.send().await.context("failed to send request")?;
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+
+        if !status.is_success() {
+            anyhow::bail!("discord returned {}: {}", status, body);
+        }
+
+        Ok(body)
+    }
+
+    async fn send_json(&self, method: Method, url: &str, command: &Value) -> Result<()> {
+        let request = self
+            .request(method, url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(command.to_string());
+
+        self.send(request).await.map(drop)
+    }
+
+    /// compares the local commands with the registered ones. nothing is changed yet.
+    pub async fn plan(&self, local: Vec<Value>) -> Result<Vec<Change>> {
+        let body = self
+            .send(self.request(Method::GET, &self.commands_url))
+            .await
+            .context("failed to get registered commands")?;
+
+        let remote = serde_json::from_str::<Vec<Value>>(&body)
+            .context("failed to parse registered commands")?;
+
+        let mut changes = vec![];
+        let mut local_keys = vec![];
+
+        for mut command in local {
+            // only global commands can be installed to users or used in DMs.
+            if let (Scope::Guild(_), Value::Object(ref mut fields)) = (&self.scope, &mut command) {
+                fields.remove("integration_types");
+                fields.remove("contexts");
+            }
+
+            let (name, ty) = key_of(&command);
+            local_keys.push((name.to_string(), ty));
+
+            match remote.iter().find(|x| key_of(x) == key_of(&command)) {
+                None => changes.push(Change::Create(command)),
+                Some(registered) if !is_same(&command, registered) => {
+                    let id = registered["id"]
+                        .as_str()
+                        .context("registered command has no id")?;
+
+                    changes.push(Change::Update {
+                        id: id.to_string(),
+                        command,
+                    });
+                }
+                Some(_) => {}
+            }
+        }
+
+        // the commands removed locally.
+        for registered in &remote {
+            let (name, ty) = key_of(registered);
+
+            if local_keys.iter().any(|(n, t)| n == name && *t == ty) {
+                continue;
+            }
+
+            let id = registered["id"]
+                .as_str()
+                .context("registered command has no id")?;
+
+            changes.push(Change::Delete {
+                id: id.to_string(),
+                name: name.to_string(),
+            });
+        }
+
+        Ok(changes)
+    }
+
+    /// sends the changes in order. stops at the first failure.
+    pub async fn apply(&self, changes: &[Change]) -> Result<()> {
+        for change in changes {
+            let result = match change {
+                Change::Create(command) => {
+                    self.send_json(Method::POST, &self.commands_url, command)
+                        .await
+                }
+                Change::Update { id, command } => {
+                    let url = format!("{}/{}", self.commands_url, id);
+                    self.send_json(Method::PATCH, &url, command).await
+                }
+                Change::Delete { id, .. } => {
+                    let url = format!("{}/{}", self.commands_url, id);
+                    self.send(self.request(Method::DELETE, &url))
+                        .await
+                        .map(drop)
+                }
+            };
+
+            result.with_context(|| format!("failed to {}", change))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use warp::http::StatusCode;
+
+    use super::*;
+    use crate::entrypoint::discord_webhook::mock_server::MockServer;
+
+    fn local() -> Value {
+        json!({
+            "name": "gmeigen",
+            "description": "Create, get or find meigen(s)",
+            "type": 1,
+            "options": [{
+                "name": "random",
+                "description": "Show meigens at random",
+                "type": 1,
+                "options": [{
+                    "name": "count",
+                    "description": "count of shown meigen",
+                    "type": 4,
+                    "required": false,
+                }],
+            }],
+            "integration_types": [0, 1],
+            "contexts": [0, 1, 2],
+        })
+    }
+
+    // what discord returns for local(). it adds its own fields and the defaults, and omits `required: false`.
+    fn registered() -> Value {
+        json!({
+            "id": "100",
+            "application_id": "1",
+            "version": "200",
+            "default_member_permissions": null,
+            "type": 1,
+            "name": "gmeigen",
+            "name_localizations": null,
+            "description": "Create, get or find meigen(s)",
+            "description_localizations": null,
+            "dm_permission": true,
+            "nsfw": false,
+            "options": [{
+                "name": "random",
+                "description": "Show meigens at random",
+                "type": 1,
+                "options": [{
+                    "name": "count",
+                    "description": "count of shown meigen",
+                    "type": 4,
+                }],
+            }],
+            "integration_types": [0, 1],
+            "contexts": [0, 1, 2],
+        })
+    }
+
+    #[test]
+    fn discord_owned_fields_and_defaults_are_ignored() {
+        assert!(is_same(&local(), &registered()));
+
+        let mut remote = registered();
+        remote["guild_id"] = json!("9");
+        assert!(is_same(&local(), &remote));
+    }
+
+    #[test]
+    fn changed_fields_differ() {
+        let mut remote = registered();
+        remote["description"] = json!("old description");
+        assert!(!is_same(&local(), &remote));
+
+        let mut remote = registered();
+        remote["options"][0]["options"][0]["required"] = json!(true);
+        assert!(!is_same(&local(), &remote));
+
+        let mut remote = registered();
+        remote["dm_permission"] = json!(false);
+        assert!(!is_same(&local(), &remote));
+
+        let mut remote = registered();
+        remote["contexts"] = json!([0]);
+        assert!(!is_same(&local(), &remote));
+    }
+
+    #[test]
+    fn fields_removed_locally_differ() {
+        let mut remote = registered();
+        remote["options"][0]["options"][0]["min_value"] = json!(1);
+        assert!(!is_same(&local(), &remote));
+
+        let mut remote = registered();
+        remote["description_localizations"] = json!({ "ja": "名言を作ったり探したりします" });
+        assert!(!is_same(&local(), &remote));
+
+        let mut remote = registered();
+        remote["options"][0]["options"]
+            .as_array_mut()
+            .unwrap()
+            .push(json!({ "name": "page", "description": "page", "type": 4 }));
+        assert!(!is_same(&local(), &remote));
+    }
+
+    #[test]
+    fn fields_added_locally_differ() {
+        let mut command = local();
+        command["options"][0]["options"][0]["autocomplete"] = json!(true);
+        assert!(!is_same(&command, &registered()));
+    }
+
+    fn command(name: &str, description: &str) -> Value {
+        json!({ "name": name, "description": description, "type": 1 })
+    }
+
+    fn registered_command(id: &str, name: &str, description: &str) -> Value {
+        json!({ "id": id, "application_id": "1", "version": "1", "name": name, "description": description, "type": 1 })
+    }
+
+    fn start_discord(registered: Vec<Value>) -> MockServer {
+        let registered = Value::from(registered).to_string();
+
+        MockServer::start(move |request| match request.method.as_str() {
+            "GET" => (StatusCode::OK, registered.clone()),
+            "DELETE" => (StatusCode::NO_CONTENT, String::new()),
+            _ => (StatusCode::OK, request.body.clone()),
+        })
+    }
+
+    #[tokio::test]
+    async fn plan_and_apply() {
+        let server = start_discord(vec![
+            registered_command("1", "same", "same"),
+            registered_command("2", "changed", "old"),
+            registered_command("3", "removed", "removed"),
+        ]);
+
+        let registrar =
+            CommandRegistrar::new(&server.url, "42", "tok".into(), Scope::Global).unwrap();
+
+        let changes = registrar
+            .plan(vec![
+                command("same", "same"),
+                command("changed", "new"),
+                command("added", "added"),
+            ])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            changes.iter().map(|x| x.to_string()).collect::<Vec<_>>(),
+            vec![
+                "update changed (id: 2)",
+                "create added",
+                "delete removed (id: 3)"
+            ]
+        );
+
+        registrar.apply(&changes).await.unwrap();
+
+        let requests = server.requests();
+
+        assert_eq!(
+            requests
+                .iter()
+                .map(|x| (x.method.as_str(), x.path.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("GET", "/applications/42/commands"),
+                ("PATCH", "/applications/42/commands/2"),
+                ("POST", "/applications/42/commands"),
+                ("DELETE", "/applications/42/commands/3"),
+            ]
+        );
+
+        assert!(requests
+            .iter()
+            .all(|x| x.authorization.as_deref() == Some("Bot tok")));
+
+        let body = |i: usize| serde_json::from_str::<Value>(&requests[i].body).unwrap();
+        assert_eq!(body(1), command("changed", "new"));
+        assert_eq!(body(2), command("added", "added"));
+    }
+
+    #[tokio::test]
+    async fn guild_commands_have_no_install_contexts() {
+        let server = start_discord(vec![]);
+
+        let registrar =
+            CommandRegistrar::new(&server.url, "42", "tok".into(), Scope::Guild("9".into()))
+                .unwrap();
+
+        let changes = registrar.plan(vec![local()]).await.unwrap();
+        registrar.apply(&changes).await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests[1].method, "POST");
+        assert_eq!(requests[1].path, "/applications/42/guilds/9/commands");
+
+        let body = serde_json::from_str::<Value>(&requests[1].body).unwrap();
+        assert_eq!(body.get("integration_types"), None);
+        assert_eq!(body.get("contexts"), None);
+    }
+
+    #[tokio::test]
+    async fn failures_are_reported() {
+        let server = MockServer::start(|_| (StatusCode::UNAUTHORIZED, "401: Unauthorized".into()));
+        let registrar =
+            CommandRegistrar::new(&server.url, "42", "tok".into(), Scope::Global).unwrap();
+
+        let error = registrar.plan(vec![]).await.err().unwrap();
+        assert!(format!("{:#}", error).contains("401: Unauthorized"));
+
+        let error = registrar
+            .apply(&[Change::Create(command("added", "added"))])
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", error).contains("failed to create added"));
+    }
+}
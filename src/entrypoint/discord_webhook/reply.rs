@@ -0,0 +1,111 @@
+use serde_json::{json, Value};
+
+use crate::{command::CommandOutput, model::Meigen};
+
+// only the user who ran the command can see the message.
+pub(super) const EPHEMERAL: u64 = 1 << 6;
+
+// (loves at least, colour). the first matching one is used.
+const COLOURS: &[(usize, u32)] = &[
+    (10, 0xe91e63), // pink
+    (5, 0xf1c40f),  // gold
+    (1, 0x3498db),  // blue
+    (0, 0x99aab5),  // grey
+];
+
+/// the message sent back to discord.
+pub(super) struct Reply {
+    pub(super) content: String,
+    /// see https://discord.com/developers/docs/resources/channel#embed-object
+    pub(super) embeds: Vec<Value>,
+    /// see https://discord.com/developers/docs/interactions/message-components
+    pub(super) components: Vec<Value>,
+    pub(super) flags: u64,
+}
+
+impl From<String> for Reply {
+    fn from(content: String) -> Self {
+        Self {
+            content,
+            embeds: vec![],
+            components: vec![],
+            flags: 0,
+        }
+    }
+}
+
+impl From<CommandOutput> for Reply {
+    fn from(output: CommandOutput) -> Self {
+        let flags = if output.is_error() { EPHEMERAL } else { 0 };
+
+        // the embeds show the meigens, so the text would be a duplicate.
+        let content = if output.meigens.is_empty() {
+            output.text
+        } else {
+            output.note
+        };
+
+        let first_rank = output.first_rank;
+
+        let embeds = output
+            .meigens
+            .iter()
+            .enumerate()
+            .map(|(i, meigen)| {
+                let mut embed = embed(meigen);
+
+                // rankings show the rank, which isn't a part of the meigen.
+                if let Some(first) = first_rank {
+                    embed["title"] = json!(format!("{}位", first + i as u32));
+                }
+
+                embed
+            })
+            .collect();
+
+        Self {
+            content,
+            embeds,
+            components: vec![],
+            flags,
+        }
+    }
+}
+
+fn embed(meigen: &Meigen) -> Value {
+    let loves = meigen.loves();
+
+    let colour = COLOURS
+        .iter()
+        .find(|(min, _)| loves >= *min)
+        .map(|(_, colour)| *colour)
+        .unwrap_or_default();
+
+    let mut fields = vec![
+        json!({ "name": "ID", "value": meigen.id.to_string(), "inline": true }),
+        json!({ "name": "♥", "value": loves.to_string(), "inline": true }),
+    ];
+
+    // mentions in embeds don't ping the user.
+    if let Some(created_by) = meigen.created_by {
+        fields
+            .push(json!({ "name": "登録", "value": format!("<@{}>", created_by), "inline": true }));
+    }
+
+    if let Some(ref source) = meigen.source {
+        fields.push(json!({ "name": "引用元", "value": source.jump_url(), "inline": true }));
+    }
+
+    let mut embed = json!({
+        "description": meigen.content,
+        "footer": { "text": format!("― {}", meigen.author) },
+        "fields": fields,
+        "color": colour,
+    });
+
+    if let Some(ref created_at) = meigen.created_at {
+        embed["timestamp"] = json!(created_at.to_rfc3339());
+    }
+
+    embed
+}
@@ -1,4 +1,8 @@
-use std::sync::Arc;
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use ring::signature::{UnparsedPublicKey, ED25519};
 use warp::{
@@ -11,13 +15,76 @@ use warp::{
 
 use super::inject;
 
+// the number of interaction ids remembered to reject the replayed requests.
+// ids are forgotten once their requests get older than max_age, as such requests are rejected anyway.
+// ids still in that window are never dropped, or their requests could be replayed.
+// new requests are rejected instead when it's full.
+const SEEN_INTERACTIONS_CAPACITY: usize = 10000;
+
+pub(super) struct Verifier {
+    public_key: Vec<u8>,
+    max_age: Duration,
+    seen: Mutex<SeenInteractions>,
+}
+
+impl Verifier {
+    pub(super) fn new(public_key: Vec<u8>, max_age: Duration) -> Self {
+        Self {
+            public_key,
+            max_age,
+            seen: Mutex::new(SeenInteractions::default()),
+        }
+    }
+}
+
+#[derive(Default)]
+struct SeenInteractions {
+    // (timestamp, interaction id) in the order they arrived.
+    order: VecDeque<(u64, String)>,
+    ids: HashSet<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Insertion {
+    Inserted,
+    Replayed,
+    Full,
+}
+
+impl SeenInteractions {
+    /// ids with the timestamp older than `oldest` are forgotten.
+    fn insert(&mut self, id: String, timestamp: u64, oldest: u64) -> Insertion {
+        if self.ids.contains(&id) {
+            return Insertion::Replayed;
+        }
+
+        // the timestamps are mostly in order, as they are checked against the current time.
+        while let Some((t, _)) = self.order.front() {
+            if *t >= oldest {
+                break;
+            }
+
+            if let Some((_, old)) = self.order.pop_front() {
+                self.ids.remove(&old);
+            }
+        }
+
+        if self.order.len() >= SEEN_INTERACTIONS_CAPACITY {
+            return Insertion::Full;
+        }
+
+        self.ids.insert(id.clone());
+        self.order.push_back((timestamp, id));
+
+        Insertion::Inserted
+    }
+}
+
 pub(super) fn filter(
-    public_key_bytes: Vec<u8>,
+    verifier: Verifier,
 ) -> impl Filter<Extract = (String,), Error = Rejection> + Clone {
-    let public_key_bytes = Arc::new(public_key_bytes);
-
     warp::any()
-        .and(inject(public_key_bytes))
+        .and(inject(Arc::new(verifier)))
         .and(warp::header::<String>("X-Signature-Ed25519"))
         .and(warp::header::<String>("X-Signature-Timestamp"))
         .and(warp::filters::body::bytes())
@@ -32,6 +99,34 @@ pub(super) fn try_recover(err: &Rejection) -> Option<WithStatus<&'static str>> {
         ));
     }
 
+    if let Some(StaleRequest) = err.find() {
+        return Some(reply_with_status(
+            "request timestamp is too old or in the future",
+            StatusCode::UNAUTHORIZED,
+        ));
+    }
+
+    if let Some(ReplayedRequest) = err.find() {
+        return Some(reply_with_status(
+            "request was already received",
+            StatusCode::UNAUTHORIZED,
+        ));
+    }
+
+    if let Some(TooManyRequests) = err.find() {
+        return Some(reply_with_status(
+            "too many requests to check for replays",
+            StatusCode::SERVICE_UNAVAILABLE,
+        ));
+    }
+
+    if let Some(InvalidBody) = err.find() {
+        return Some(reply_with_status(
+            "body is not valid utf-8",
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+
     None
 }
 
@@ -39,8 +134,24 @@ pub(super) fn try_recover(err: &Rejection) -> Option<WithStatus<&'static str>> {
 struct SignatureVerifyError;
 impl Reject for SignatureVerifyError {}
 
+#[derive(Debug)]
+struct StaleRequest;
+impl Reject for StaleRequest {}
+
+#[derive(Debug)]
+struct ReplayedRequest;
+impl Reject for ReplayedRequest {}
+
+#[derive(Debug)]
+struct TooManyRequests;
+impl Reject for TooManyRequests {}
+
+#[derive(Debug)]
+struct InvalidBody;
+impl Reject for InvalidBody {}
+
 async fn verify_signature(
-    key: Arc<Vec<u8>>,
+    verifier: Arc<Verifier>,
     signature: String,
     timestamp: String,
     body: Bytes,
@@ -50,11 +161,10 @@ async fn verify_signature(
         reject_custom(SignatureVerifyError)
     })?;
 
-    let body = String::from_utf8(body.to_vec()).expect("failed to parse as utf-8");
-    let data = format!("{}{}", timestamp, body);
+    let data = [timestamp.as_bytes(), &body].concat();
 
-    UnparsedPublicKey::new(&ED25519, key.as_slice())
-        .verify(data.as_bytes(), &signature)
+    UnparsedPublicKey::new(&ED25519, verifier.public_key.as_slice())
+        .verify(&data, &signature)
         .map_err(|e| {
             tracing::trace!("failed to verify signature: {}", e);
             reject_custom(SignatureVerifyError)
@@ -62,5 +172,221 @@ async fn verify_signature(
 
     tracing::trace!("no error reported while verifying signature");
 
+    // the timestamp is signed too, so it can be trusted from here.
+    let timestamp = timestamp.parse::<u64>().map_err(|_| {
+        tracing::trace!("failed to parse timestamp");
+        reject_custom(SignatureVerifyError)
+    })?;
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    let max_age = verifier.max_age.as_secs();
+
+    // allow the clocks to differ in both directions.
+    if now.abs_diff(timestamp) > max_age {
+        tracing::info!("rejected a request signed at {} (now: {})", timestamp, now);
+        return Err(reject_custom(StaleRequest));
+    }
+
+    let body = String::from_utf8(body.to_vec()).map_err(|_| {
+        tracing::info!("request body is not valid utf-8");
+        reject_custom(InvalidBody)
+    })?;
+
+    #[derive(serde::Deserialize)]
+    struct Interaction {
+        id: String,
+    }
+
+    // the body without id is rejected later as invalid json.
+    if let Ok(interaction) = serde_json::from_str::<Interaction>(&body) {
+        let insertion = verifier.seen.lock().unwrap().insert(
+            interaction.id,
+            timestamp,
+            now.saturating_sub(max_age),
+        );
+
+        match insertion {
+            Insertion::Inserted => {}
+            Insertion::Replayed => {
+                tracing::info!("rejected a replayed request");
+                return Err(reject_custom(ReplayedRequest));
+            }
+            Insertion::Full => {
+                tracing::warn!("rejected a request as too many requests are waiting to expire");
+                return Err(reject_custom(TooManyRequests));
+            }
+        }
+    }
+
     Ok(body)
 }
+
+#[cfg(test)]
+mod tests {
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    use super::*;
+
+    const MAX_AGE: Duration = Duration::from_secs(60);
+
+    struct Signer {
+        key_pair: Ed25519KeyPair,
+        verifier: Arc<Verifier>,
+    }
+
+    impl Signer {
+        fn new() -> Self {
+            let key_pair = Ed25519KeyPair::from_seed_unchecked(&[7; 32]).unwrap();
+            let verifier = Verifier::new(key_pair.public_key().as_ref().to_vec(), MAX_AGE);
+
+            Self {
+                key_pair,
+                verifier: Arc::new(verifier),
+            }
+        }
+
+        /// signs the body as discord does, and verifies it.
+        async fn send(&self, timestamp: u64, body: &[u8]) -> Result<String, Rejection> {
+            let timestamp = timestamp.to_string();
+            let signature = self.key_pair.sign(&[timestamp.as_bytes(), body].concat());
+
+            self.verify(hex::encode(signature), timestamp, body).await
+        }
+
+        async fn verify(
+            &self,
+            signature: String,
+            timestamp: String,
+            body: &[u8],
+        ) -> Result<String, Rejection> {
+            verify_signature(
+                Arc::clone(&self.verifier),
+                signature,
+                timestamp,
+                Bytes::copy_from_slice(body),
+            )
+            .await
+        }
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    fn interaction(id: &str) -> Vec<u8> {
+        format!(r#"{{"id":"{}","type":1}}"#, id).into_bytes()
+    }
+
+    fn is<T: Reject>(result: Result<String, Rejection>) -> bool {
+        matches!(result, Err(ref e) if e.find::<T>().is_some())
+    }
+
+    #[tokio::test]
+    async fn valid_request() {
+        let signer = Signer::new();
+        let body = interaction("1");
+
+        assert_eq!(
+            signer.send(now(), &body).await.unwrap(),
+            String::from_utf8(body).unwrap()
+        );
+
+        // the clocks may differ a little.
+        assert!(signer.send(now() - 30, &interaction("2")).await.is_ok());
+        assert!(signer.send(now() + 30, &interaction("3")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn bad_signature() {
+        let signer = Signer::new();
+        let timestamp = now().to_string();
+
+        let signature = signer
+            .key_pair
+            .sign(&[timestamp.as_bytes(), &interaction("other")].concat());
+
+        let result = signer
+            .verify(hex::encode(signature), timestamp.clone(), &interaction("1"))
+            .await;
+        assert!(is::<SignatureVerifyError>(result));
+
+        let result = signer
+            .verify("not hex".into(), timestamp, &interaction("1"))
+            .await;
+        assert!(is::<SignatureVerifyError>(result));
+
+        // the timestamp is a part of the signed data.
+        let signature = signer.key_pair.sign(&interaction("1"));
+        let result = signer
+            .verify(hex::encode(signature), now().to_string(), &interaction("1"))
+            .await;
+        assert!(is::<SignatureVerifyError>(result));
+    }
+
+    #[tokio::test]
+    async fn stale_timestamp() {
+        let signer = Signer::new();
+        let result = signer.send(now() - 120, &interaction("1")).await;
+
+        assert!(is::<StaleRequest>(result));
+    }
+
+    #[tokio::test]
+    async fn future_timestamp() {
+        let signer = Signer::new();
+        let result = signer.send(now() + 120, &interaction("1")).await;
+
+        assert!(is::<StaleRequest>(result));
+    }
+
+    #[tokio::test]
+    async fn replayed_id() {
+        let signer = Signer::new();
+
+        assert!(signer.send(now(), &interaction("1")).await.is_ok());
+        assert!(is::<ReplayedRequest>(
+            signer.send(now(), &interaction("1")).await
+        ));
+
+        // signed again with another timestamp, but the same interaction.
+        assert!(is::<ReplayedRequest>(
+            signer.send(now() - 1, &interaction("1")).await
+        ));
+
+        assert!(signer.send(now(), &interaction("2")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn non_utf8_body() {
+        let signer = Signer::new();
+        let result = signer.send(now(), &[b'{', 0xff, 0xfe, b'}']).await;
+
+        assert!(is::<InvalidBody>(result));
+    }
+
+    #[test]
+    fn full_cache_rejects_instead_of_forgetting() {
+        let mut seen = SeenInteractions::default();
+
+        for id in 0..SEEN_INTERACTIONS_CAPACITY {
+            assert_eq!(seen.insert(id.to_string(), 1000, 900), Insertion::Inserted);
+        }
+
+        assert_eq!(seen.insert("new".into(), 1000, 900), Insertion::Full);
+
+        // every id in the window is still remembered.
+        assert_eq!(seen.insert("0".into(), 1000, 900), Insertion::Replayed);
+
+        // once they get old, they are forgotten and there is room again.
+        assert_eq!(seen.insert("new".into(), 2000, 1900), Insertion::Inserted);
+        assert_eq!(seen.insert("0".into(), 2000, 1900), Insertion::Inserted);
+        assert_eq!(seen.order.len(), 2);
+    }
+}
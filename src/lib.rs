@@ -2,6 +2,9 @@ pub mod command;
 pub mod db;
 pub mod entrypoint;
 pub mod model;
+pub mod normalize;
+pub mod permission;
+pub mod query;
 pub mod util;
 
 pub type Synced<T> = std::sync::Arc<tokio::sync::RwLock<T>>;
@@ -1,3 +1,4 @@
+use chrono::{DateTime, FixedOffset, Utc};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -6,6 +7,41 @@ pub struct Meigen {
     pub author: String,
     pub content: String,
     pub loved_user_id: Vec<u64>,
+
+    // These are None for meigens registered before they were introduced.
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    /// Discord user id of the one who registered this meigen.
+    #[serde(default)]
+    pub created_by: Option<u64>,
+    /// when the author or the content was changed last time.
+    #[serde(default)]
+    pub updated_at: Option<DateTime<Utc>>,
+    /// the discord message this meigen was quoted from. None if it was typed by hand.
+    #[serde(default)]
+    pub source: Option<MeigenSource>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MeigenSource {
+    /// None if the message was sent in DM.
+    pub guild_id: Option<u64>,
+    pub channel_id: u64,
+    pub message_id: u64,
+}
+
+impl MeigenSource {
+    pub fn jump_url(&self) -> String {
+        let guild = match self.guild_id {
+            Some(id) => id.to_string(),
+            None => "@me".into(),
+        };
+
+        format!(
+            "https://discord.com/channels/{}/{}/{}",
+            guild, self.channel_id, self.message_id
+        )
+    }
 }
 impl Meigen {
     pub fn loves(&self) -> usize {
@@ -17,6 +53,14 @@ impl Meigen {
     }
 }
 
+fn format_time(time: &DateTime<Utc>) -> String {
+    // most of the users live in Japan.
+    let jst = FixedOffset::east_opt(9 * 60 * 60).unwrap();
+    time.with_timezone(&jst)
+        .format("%Y/%m/%d %H:%M")
+        .to_string()
+}
+
 impl std::fmt::Display for Meigen {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let loves = self.loves();
@@ -26,14 +70,98 @@ impl std::fmt::Display for Meigen {
             "".to_string()
         };
 
+        let mut metadata = String::new();
+
+        if let Some(ref created_at) = self.created_at {
+            metadata += &format!(" 登録: {}", format_time(created_at));
+        }
+
+        if let Some(ref updated_at) = self.updated_at {
+            if self.created_at.as_ref() != Some(updated_at) {
+                metadata += &format!(" 更新: {}", format_time(updated_at));
+            }
+        }
+
         write!(
             f,
-            "Meigen No.{} {}
+            "Meigen No.{} {}{}
 ```
 {}
     --- {}
 ```",
-            self.id, loves_description, self.content, self.author
+            self.id, loves_description, metadata, self.content, self.author
         )
     }
 }
+
+/// loves summed up per author.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthorStats {
+    pub author: String,
+    pub loves: u32,
+    /// how many meigens the author has.
+    pub meigens: u32,
+}
+
+/// a change made to a meigen.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub meigen_id: u32,
+    pub kind: HistoryKind,
+    /// Discord user id of the one who made this change.
+    pub actor: u64,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HistoryKind {
+    Create,
+    Edit {
+        previous_author: String,
+        previous_content: String,
+    },
+    Delete,
+    Restore,
+    Love,
+    Unlove,
+}
+
+impl HistoryKind {
+    /// whether the change loses something that admins may want to take back.
+    pub fn is_destructive(&self) -> bool {
+        matches!(self, HistoryKind::Edit { .. } | HistoryKind::Delete)
+    }
+}
+
+impl std::fmt::Display for HistoryEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let action = match self.kind {
+            HistoryKind::Create => "登録",
+            HistoryKind::Edit { .. } => "編集",
+            HistoryKind::Delete => "削除",
+            HistoryKind::Restore => "復元",
+            HistoryKind::Love => "いいね",
+            HistoryKind::Unlove => "いいね取り消し",
+        };
+
+        write!(
+            f,
+            "{} No.{} {} <@{}>",
+            format_time(&self.at),
+            self.meigen_id,
+            action,
+            self.actor
+        )?;
+
+        if let HistoryKind::Edit {
+            ref previous_author,
+            ref previous_content,
+        } = self.kind
+        {
+            write!(f, " (変更前: {} --- {})", previous_content, previous_author)?;
+        }
+
+        Ok(())
+    }
+}
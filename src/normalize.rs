@@ -0,0 +1,22 @@
+use unicode_normalization::UnicodeNormalization;
+
+/// folds the text so that searching ignores the differences users don't care about.
+/// every database must compare normalized texts with each other, so that they return the same result.
+///
+/// - NFKC: ｶﾀｶﾅ → カタカナ, ＡＢＣ → ABC
+/// - case folding: ABC → abc
+/// - kana folding: カタカナ → かたかな
+pub fn normalize(text: &str) -> String {
+    text.nfkc()
+        .flat_map(char::to_lowercase)
+        .map(katakana_to_hiragana)
+        .collect()
+}
+
+fn katakana_to_hiragana(c: char) -> char {
+    // ァ(U+30A1) ..= ヶ(U+30F6) are placed at the same order as ぁ(U+3041) ..= ゖ(U+3096).
+    match c {
+        'ァ'..='ヶ' => std::char::from_u32(c as u32 - 0x60).unwrap_or(c),
+        c => c,
+    }
+}
@@ -0,0 +1,125 @@
+use std::{collections::HashSet, env::VarError};
+
+use anyhow::{Context as _, Result};
+
+use crate::model::Meigen;
+
+/// user id used by the console.
+/// anyone who can run the console already has the access to the database.
+pub const CONSOLE_USER_ID: u64 = 0;
+
+/// the user who is running the command.
+#[derive(Debug, Clone)]
+pub struct Actor {
+    pub user_id: u64,
+
+    /// ids of Discord roles the user has. empty if the request didn't come from a guild.
+    pub role_ids: Vec<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Member,
+    Moderator,
+    Admin,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Action {
+    Delete,
+    Restore,
+    /// editing meigens registered by someone else.
+    Edit,
+    /// listing recent destructive changes.
+    ViewAudit,
+}
+
+impl Action {
+    fn required_role(self) -> Role {
+        match self {
+            Action::Delete | Action::Restore => Role::Moderator,
+            Action::Edit | Action::ViewAudit => Role::Admin,
+        }
+    }
+}
+
+/// who is admin or moderator. every entrypoint must ask this before running privileged operations.
+#[derive(Debug, Clone, Default)]
+pub struct Permissions {
+    admin_user_ids: HashSet<u64>,
+    admin_role_ids: HashSet<u64>,
+    moderator_user_ids: HashSet<u64>,
+    moderator_role_ids: HashSet<u64>,
+}
+
+impl Permissions {
+    /// loads comma separated id lists from MEIGEN_ADMIN_USER_IDS, MEIGEN_ADMIN_ROLE_IDS,
+    /// MEIGEN_MODERATOR_USER_IDS and MEIGEN_MODERATOR_ROLE_IDS.
+    pub fn from_env() -> Result<Self> {
+        let permissions = Self {
+            admin_user_ids: ids_from_env("MEIGEN_ADMIN_USER_IDS")?,
+            admin_role_ids: ids_from_env("MEIGEN_ADMIN_ROLE_IDS")?,
+            moderator_user_ids: ids_from_env("MEIGEN_MODERATOR_USER_IDS")?,
+            moderator_role_ids: ids_from_env("MEIGEN_MODERATOR_ROLE_IDS")?,
+        };
+
+        if permissions.admin_user_ids.is_empty() && permissions.admin_role_ids.is_empty() {
+            tracing::warn!("no admin is configured. nobody can run admin only commands.");
+        }
+
+        Ok(permissions)
+    }
+
+    /// permissions for the console, where the operator is admin.
+    pub fn console() -> Self {
+        let mut permissions = Self::default();
+        permissions.admin_user_ids.insert(CONSOLE_USER_ID);
+        permissions
+    }
+
+    pub fn role_of(&self, actor: &Actor) -> Role {
+        let has_any = |users: &HashSet<u64>, roles: &HashSet<u64>| {
+            users.contains(&actor.user_id) || actor.role_ids.iter().any(|x| roles.contains(x))
+        };
+
+        if has_any(&self.admin_user_ids, &self.admin_role_ids) {
+            return Role::Admin;
+        }
+
+        if has_any(&self.moderator_user_ids, &self.moderator_role_ids) {
+            return Role::Moderator;
+        }
+
+        Role::Member
+    }
+
+    pub fn is_allowed(&self, actor: &Actor, action: Action) -> bool {
+        self.role_of(actor) >= action.required_role()
+    }
+
+    pub fn can_edit(&self, actor: &Actor, meigen: &Meigen) -> bool {
+        meigen.created_by == Some(actor.user_id) || self.is_allowed(actor, Action::Edit)
+    }
+
+    pub fn admin_user_ids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.admin_user_ids.iter().copied()
+    }
+}
+
+fn ids_from_env(name: &str) -> Result<HashSet<u64>> {
+    let value = match std::env::var(name) {
+        Ok(v) => v,
+        Err(VarError::NotPresent) => return Ok(HashSet::new()),
+        Err(e) => return Err(e).with_context(|| format!("failed to get {}", name)),
+    };
+
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .map(|x| {
+            x.parse()
+                .with_context(|| format!("{} contains invalid id: {}", name, x))
+        })
+        .collect()
+}
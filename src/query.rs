@@ -0,0 +1,374 @@
+//! search query like `author:かわえもん content:"草" loves:>=3 id:100..200 -word`.
+//!
+//! - `author:` and `content:` narrow down the field. can be specified only once.
+//! - `loves:` and `id:` take `3`, `>=3`, `>3`, `<=3`, `<3`, `3..5`, `3..` or `..5`.
+//! - bare words must be contained in either author or content.
+//! - words starting with `-` must not be contained in both of them.
+//! - double quotes let the value contain spaces.
+
+use crate::db::{Bounds, FindOptions, SortOrder};
+
+/// in characters. longer queries are rejected before parsing.
+pub const QUERY_LENGTH_LIMIT: usize = 200;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query {
+    pub author: Option<String>,
+    pub content: Option<String>,
+    pub keywords: Vec<String>,
+    pub excludes: Vec<String>,
+    pub loves: Bounds,
+    pub id: Bounds,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    UnclosedQuote,
+    EmptyValue(&'static str),
+    Duplicated(&'static str),
+    Negated(&'static str),
+    InvalidRange { field: &'static str, value: String },
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::UnclosedQuote => write!(f, "\"が閉じられていません"),
+            ParseError::EmptyValue(field) => write!(f, "{}:の後に値がありません", field),
+            ParseError::Duplicated(field) => write!(f, "{}:は一度しか指定できません", field),
+            ParseError::Negated(field) => write!(f, "{}:に-は付けられません", field),
+            ParseError::InvalidRange { field, value } => {
+                write!(f, "{}:の値「{}」は範囲として解釈できません", field, value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+struct Token {
+    negated: bool,
+    key: Option<String>,
+    value: String,
+}
+
+impl Query {
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let mut query = Query::default();
+
+        for token in tokenize(text)? {
+            let field = match token.key.as_deref() {
+                Some("author") => "author",
+                Some("content") => "content",
+                Some("loves") => "loves",
+                Some("id") => "id",
+
+                // unknown keys are just a part of the word. e.g. "https://..."
+                Some(key) => {
+                    let word = format!("{}:{}", key, token.value);
+                    query.push_word(token.negated, word);
+                    continue;
+                }
+
+                None => {
+                    if !token.value.is_empty() {
+                        query.push_word(token.negated, token.value);
+                    }
+                    continue;
+                }
+            };
+
+            if token.negated {
+                return Err(ParseError::Negated(field));
+            }
+
+            if token.value.is_empty() {
+                return Err(ParseError::EmptyValue(field));
+            }
+
+            match field {
+                "author" => set_once(&mut query.author, field, token.value)?,
+                "content" => set_once(&mut query.content, field, token.value)?,
+                "loves" => query.loves = query.loves.intersect(parse_bounds(field, &token.value)?),
+                "id" => query.id = query.id.intersect(parse_bounds(field, &token.value)?),
+                _ => unreachable!(),
+            }
+        }
+
+        Ok(query)
+    }
+
+    fn push_word(&mut self, negated: bool, word: String) {
+        if negated {
+            self.excludes.push(word);
+        } else {
+            self.keywords.push(word);
+        }
+    }
+
+    pub fn find_options(&self, sort: SortOrder, offset: u32, limit: u8) -> FindOptions<'_> {
+        FindOptions {
+            author: self.author.as_deref(),
+            content: self.content.as_deref(),
+            keywords: self.keywords.iter().map(|x| x.as_str()).collect(),
+            excludes: self.excludes.iter().map(|x| x.as_str()).collect(),
+            loves: self.loves,
+            id: self.id,
+            sort,
+            older_than: None,
+            newer_than: None,
+            offset,
+            limit,
+        }
+    }
+}
+
+fn set_once(
+    slot: &mut Option<String>,
+    field: &'static str,
+    value: String,
+) -> Result<(), ParseError> {
+    if slot.is_some() {
+        return Err(ParseError::Duplicated(field));
+    }
+
+    *slot = Some(value);
+    Ok(())
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        if chars.peek().is_none() {
+            return Ok(tokens);
+        }
+
+        let negated = chars.next_if_eq(&'-').is_some();
+        let mut key = None;
+        let mut value = String::new();
+        let mut quoted = false;
+        let mut seen_quote = false;
+
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() && !quoted {
+                break;
+            }
+
+            chars.next();
+
+            match c {
+                '"' => {
+                    quoted = !quoted;
+                    seen_quote = true;
+                }
+
+                // colons in quotes or after the first one are the part of the value.
+                ':' if !seen_quote && key.is_none() => key = Some(std::mem::take(&mut value)),
+
+                c => value.push(c),
+            }
+        }
+
+        if quoted {
+            return Err(ParseError::UnclosedQuote);
+        }
+
+        tokens.push(Token {
+            negated,
+            key,
+            value,
+        });
+    }
+}
+
+fn parse_bounds(field: &'static str, value: &str) -> Result<Bounds, ParseError> {
+    let invalid = || ParseError::InvalidRange {
+        field,
+        value: value.to_string(),
+    };
+
+    let number = |x: &str| x.parse::<u32>().map_err(|_| invalid());
+    let optional_number = |x: &str| match x {
+        "" => Ok(None),
+        x => number(x).map(Some),
+    };
+
+    let bounds = if let Some((min, max)) = value.split_once("..") {
+        Bounds {
+            min: optional_number(min)?,
+            max: optional_number(max)?,
+        }
+    } else if let Some(x) = value.strip_prefix(">=") {
+        Bounds {
+            min: Some(number(x)?),
+            max: None,
+        }
+    } else if let Some(x) = value.strip_prefix("<=") {
+        Bounds {
+            min: None,
+            max: Some(number(x)?),
+        }
+    } else if let Some(x) = value.strip_prefix('>') {
+        Bounds {
+            min: Some(number(x)?.checked_add(1).ok_or_else(invalid)?),
+            max: None,
+        }
+    } else if let Some(x) = value.strip_prefix('<') {
+        Bounds {
+            min: None,
+            max: Some(number(x)?.checked_sub(1).ok_or_else(invalid)?),
+        }
+    } else {
+        let x = number(value.strip_prefix('=').unwrap_or(value))?;
+
+        Bounds {
+            min: Some(x),
+            max: Some(x),
+        }
+    };
+
+    Ok(bounds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bounds(min: Option<u32>, max: Option<u32>) -> Bounds {
+        Bounds { min, max }
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(Query::parse(""), Ok(Query::default()));
+        assert_eq!(Query::parse("   \t "), Ok(Query::default()));
+    }
+
+    #[test]
+    fn words_and_fields() {
+        let query = Query::parse("foo author:かわえもん bar content:草").unwrap();
+
+        assert_eq!(query.author.as_deref(), Some("かわえもん"));
+        assert_eq!(query.content.as_deref(), Some("草"));
+        assert_eq!(query.keywords, vec!["foo", "bar"]);
+        assert!(query.excludes.is_empty());
+    }
+
+    #[test]
+    fn quoting() {
+        let query = Query::parse(r#"author:"foo bar" "baz qux" content:"a:b" -"x y""#).unwrap();
+
+        assert_eq!(query.author.as_deref(), Some("foo bar"));
+        assert_eq!(query.content.as_deref(), Some("a:b"));
+        assert_eq!(query.keywords, vec!["baz qux"]);
+        assert_eq!(query.excludes, vec!["x y"]);
+
+        // a quoted key is a plain word.
+        let query = Query::parse(r#""author:foo""#).unwrap();
+        assert_eq!(query.author, None);
+        assert_eq!(query.keywords, vec!["author:foo"]);
+
+        assert_eq!(
+            Query::parse(r#"author:"foo"#),
+            Err(ParseError::UnclosedQuote)
+        );
+        assert_eq!(Query::parse(r#"""#), Err(ParseError::UnclosedQuote));
+    }
+
+    #[test]
+    fn excludes() {
+        let query = Query::parse("-foo bar -https://example.com").unwrap();
+
+        assert_eq!(query.keywords, vec!["bar"]);
+        assert_eq!(query.excludes, vec!["foo", "https://example.com"]);
+
+        assert_eq!(
+            Query::parse("-author:foo"),
+            Err(ParseError::Negated("author"))
+        );
+        assert_eq!(Query::parse("-id:3"), Err(ParseError::Negated("id")));
+
+        // a lone "-" excludes nothing.
+        assert_eq!(Query::parse("- foo").unwrap().keywords, vec!["foo"]);
+        assert!(Query::parse("- foo").unwrap().excludes.is_empty());
+    }
+
+    #[test]
+    fn field_errors() {
+        assert_eq!(
+            Query::parse("author:a author:b"),
+            Err(ParseError::Duplicated("author"))
+        );
+        assert_eq!(
+            Query::parse("content:"),
+            Err(ParseError::EmptyValue("content"))
+        );
+        assert_eq!(
+            Query::parse(r#"loves:"""#),
+            Err(ParseError::EmptyValue("loves"))
+        );
+    }
+
+    #[test]
+    fn bounds_syntax() {
+        let loves = |x: &str| Query::parse(&format!("loves:{}", x)).map(|q| q.loves);
+
+        assert_eq!(loves("3"), Ok(bounds(Some(3), Some(3))));
+        assert_eq!(loves("=3"), Ok(bounds(Some(3), Some(3))));
+        assert_eq!(loves(">=3"), Ok(bounds(Some(3), None)));
+        assert_eq!(loves(">3"), Ok(bounds(Some(4), None)));
+        assert_eq!(loves("<=3"), Ok(bounds(None, Some(3))));
+        assert_eq!(loves("<3"), Ok(bounds(None, Some(2))));
+        assert_eq!(loves("3..5"), Ok(bounds(Some(3), Some(5))));
+        assert_eq!(loves("3.."), Ok(bounds(Some(3), None)));
+        assert_eq!(loves("..5"), Ok(bounds(None, Some(5))));
+        assert_eq!(loves(".."), Ok(bounds(None, None)));
+    }
+
+    #[test]
+    fn bounds_are_intersected() {
+        let query = Query::parse("id:100..200 id:>=150 loves:>1 loves:<10").unwrap();
+
+        assert_eq!(query.id, bounds(Some(150), Some(200)));
+        assert_eq!(query.loves, bounds(Some(2), Some(9)));
+    }
+
+    #[test]
+    fn malformed_ranges() {
+        for value in [
+            "abc",
+            "-1",
+            "1...2",
+            "1..2..3",
+            "a..5",
+            "3..b",
+            ">=",
+            ">",
+            "<0",
+            ">4294967295",
+            "4294967296",
+            "1.5",
+        ] {
+            assert_eq!(
+                Query::parse(&format!("id:{}", value)),
+                Err(ParseError::InvalidRange {
+                    field: "id",
+                    value: value.to_string(),
+                }),
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_keys_are_words() {
+        let query = Query::parse("https://example.com foo:bar:baz").unwrap();
+
+        assert_eq!(query.keywords, vec!["https://example.com", "foo:bar:baz"]);
+    }
+}